@@ -0,0 +1,45 @@
+use actix_web::http::StatusCode;
+use actix_web::web::JsonConfig;
+use actix_web::{HttpResponse, ResponseError};
+use mafia::{Error as MError, Response};
+use std::fmt;
+
+/// Wraps a game error so it can be returned from a handler with a sensible
+/// status code and the same `Error` response clients get over the websocket.
+#[derive(Debug)]
+pub struct ApiError(pub MError);
+
+impl From<MError> for ApiError {
+    fn from(e: MError) -> Self {
+        ApiError(e)
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn error_response(&self) -> HttpResponse {
+        use MError::*;
+        let status = match &self.0 {
+            InvalidSession | UnknownPreset(_) => StatusCode::NOT_FOUND,
+            InvalidSecret => StatusCode::UNAUTHORIZED,
+            NotHost | Banned(_) => StatusCode::FORBIDDEN,
+            LobbyClosed => StatusCode::GONE,
+            PlayerNameTaken(_) | GameInProgress => StatusCode::CONFLICT,
+            InternalError => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        HttpResponse::build(status).json(Response::from(self.0.clone()))
+    }
+}
+
+/// Makes `web::Json` reject a malformed body the same way a malformed
+/// websocket action is rejected.
+pub fn json_config() -> JsonConfig {
+    JsonConfig::default()
+        .error_handler(|err, _| ApiError(MError::MalformedAction(err.to_string())).into())
+}
@@ -1,20 +1,30 @@
+mod api_error;
+mod persist;
+mod presets;
+
 use actix::dev::{MessageResponse, ResponseChannel};
 use actix::fut::{err, wrap_future};
 use actix::prelude::Future;
 use actix::{
     Actor, ActorContext, ActorFuture, Addr, AsyncContext, Context, Handler, Message,
-    ResponseActFuture, Running, StreamHandler, WeakAddr,
+    ResponseActFuture, Running, SpawnHandle, StreamHandler, WeakAddr,
 };
-use actix_web::error::InternalError;
-use actix_web::http::StatusCode;
 use actix_web::middleware::Logger;
 use actix_web::{web, App, Error, HttpRequest, HttpResponse, HttpServer};
 use actix_web_actors::ws;
+use api_error::{json_config, ApiError};
+use chrono::{DateTime, Utc};
 use log::{debug, error};
-use mafia::{Error as MError, PlayerConnection, Response, Ruleset, State};
+use mafia::{
+    Action, Error as MError, GameSummary, PhaseName, PlayerConnection, Recap, Response,
+    RulesUpdate, Ruleset, State,
+};
+use persist::{FsStore, Store};
+use presets::Presets;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 use uuid::Uuid;
 
 fn gen_uuid() -> String {
@@ -24,6 +34,18 @@ fn gen_uuid() -> String {
     s.to_string()
 }
 
+/// Letters that can't be mistaken for one another, or for digits, when a
+/// code is read aloud or copied by hand.
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKMNPQRSTUVWXYZ";
+const CODE_LEN: usize = 5;
+
+fn gen_code() -> String {
+    Uuid::new_v4().as_bytes()[..CODE_LEN]
+        .iter()
+        .map(|b| CODE_ALPHABET[*b as usize % CODE_ALPHABET.len()] as char)
+        .collect()
+}
+
 use once_cell::sync::Lazy;
 
 static CONNECTION_MANAGER: Lazy<Addr<ConnectionManager>> =
@@ -88,14 +110,114 @@ impl Handler<Disconnect> for ConnectionManager {
     }
 }
 
+/// How often sessions check whether they should be torn down.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
 struct SessionManager {
     sessions: HashMap<String, Addr<GameSession>>,
+    /// join code -> session id
+    codes: HashMap<String, String>,
+    /// Public games still in the lobby, kept up to date by the sessions.
+    listings: HashMap<String, LobbyListing>,
+    store: Arc<dyn Store>,
+    presets: Presets,
+    timeouts: Timeouts,
+}
+
+/// How long sessions wait before giving up on absent players.
+#[derive(Clone, Copy)]
+struct Timeouts {
+    /// How long a game is kept around with nobody connected.
+    idle: chrono::Duration,
+    /// How long the host can be gone before someone else takes over.
+    host: chrono::Duration,
+    /// How long a finished game is kept around so its recap can be fetched.
+    recap: chrono::Duration,
+}
+
+impl Timeouts {
+    fn from_env() -> Self {
+        Timeouts {
+            idle: env_secs("MAFIA_IDLE_TIMEOUT", 30 * 60),
+            host: env_secs("MAFIA_HOST_TIMEOUT", 2 * 60),
+            recap: env_secs("MAFIA_RECAP_TTL", 24 * 60 * 60),
+        }
+    }
+}
+
+fn env_secs(key: &str, default: i64) -> chrono::Duration {
+    let secs: i64 = std::env::var(key)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(default);
+    // chrono panics on anything it can't hold in milliseconds
+    chrono::Duration::seconds(secs.clamp(0, i64::MAX / 1000))
 }
 
 impl Default for SessionManager {
     fn default() -> Self {
+        let dir = std::env::var("MAFIA_DATA_DIR").unwrap_or_else(|_| "sessions".to_string());
+        let preset_dir =
+            std::env::var("MAFIA_PRESET_DIR").unwrap_or_else(|_| "presets".to_string());
         SessionManager {
             sessions: HashMap::new(),
+            codes: HashMap::new(),
+            listings: HashMap::new(),
+            store: Arc::new(FsStore::new(dir)),
+            presets: Presets::load(preset_dir),
+            timeouts: Timeouts::from_env(),
+        }
+    }
+}
+
+impl SessionManager {
+    fn env(&self, ctx: &mut Context<Self>) -> SessionEnv {
+        SessionEnv {
+            store: self.store.clone(),
+            manager: ctx.address(),
+            timeouts: self.timeouts,
+        }
+    }
+
+    fn new_code(&self) -> String {
+        loop {
+            let code = gen_code();
+            if !self.codes.contains_key(&code) {
+                return code;
+            }
+        }
+    }
+
+    /// Looks a session up by its join code. The internal id is never
+    /// accepted, it's only for keeping track of sessions.
+    fn find(&self, code: &str) -> Option<Addr<GameSession>> {
+        let id = self.codes.get(&code.to_uppercase())?;
+        self.sessions.get(id).cloned()
+    }
+
+    fn restore_sessions(&mut self, ctx: &mut Context<Self>) {
+        let saved = match self.store.load_all() {
+            Ok(saved) => saved,
+            Err(e) => {
+                error!("error loading saved sessions: {}", e);
+                return;
+            }
+        };
+        for (id, data) in saved {
+            match serde_json::from_str::<SavedSession>(&data) {
+                Ok(saved) => {
+                    let code = saved
+                        .code
+                        .clone()
+                        .filter(|c| !self.codes.contains_key(c))
+                        .unwrap_or_else(|| self.new_code());
+                    debug!("restored session {} as {}", id, code);
+                    self.codes.insert(code.clone(), id.clone());
+                    let session = GameSession::restore(id.clone(), code, saved, self.env(ctx));
+                    self.sessions.insert(id, session.start());
+                }
+                Err(e) => error!("error restoring session {}: {}", id, e),
+            }
         }
     }
 }
@@ -103,8 +225,9 @@ impl Default for SessionManager {
 impl Actor for SessionManager {
     type Context = Context<Self>;
 
-    fn started(&mut self, _: &mut Self::Context) {
+    fn started(&mut self, ctx: &mut Self::Context) {
         debug!("session manager started");
+        self.restore_sessions(ctx);
     }
 
     fn stopping(&mut self, _: &mut Self::Context) -> Running {
@@ -127,10 +250,80 @@ impl Handler<RegisterSession> for SessionManager {
     }
 }
 
-struct GetSession {
+#[derive(Message)]
+struct UpdateListing {
+    id: String,
+    /// `None` takes the game off the lobby list.
+    listing: Option<LobbyListing>,
+}
+
+impl Handler<UpdateListing> for SessionManager {
+    type Result = ();
+
+    fn handle(&mut self, msg: UpdateListing, _: &mut Self::Context) -> Self::Result {
+        match msg.listing {
+            Some(listing) => self.listings.insert(msg.id, listing),
+            None => self.listings.remove(&msg.id),
+        };
+    }
+}
+
+#[derive(Message)]
+struct RemoveSession {
     id: String,
 }
 
+impl Handler<RemoveSession> for SessionManager {
+    type Result = ();
+
+    fn handle(&mut self, msg: RemoveSession, _: &mut Self::Context) -> Self::Result {
+        debug!("removing session {}", msg.id);
+        self.sessions.remove(&msg.id);
+        self.codes.retain(|_, id| *id != msg.id);
+        self.listings.remove(&msg.id);
+    }
+}
+
+struct ListLobbies;
+
+impl Message for ListLobbies {
+    type Result = Lobbies;
+}
+
+impl Handler<ListLobbies> for SessionManager {
+    type Result = Lobbies;
+
+    fn handle(&mut self, _: ListLobbies, _: &mut Self::Context) -> Self::Result {
+        Lobbies(self.listings.values().cloned().collect())
+    }
+}
+
+#[derive(Clone, Serialize)]
+struct LobbyListing {
+    code: String,
+    #[serde(flatten)]
+    summary: GameSummary,
+}
+
+struct Lobbies(Vec<LobbyListing>);
+
+impl MessageResponse<SessionManager, ListLobbies> for Lobbies {
+    fn handle<R: ResponseChannel<ListLobbies>>(
+        self,
+        _: &mut <SessionManager as Actor>::Context,
+        tx: Option<R>,
+    ) {
+        if let Some(chan) = tx {
+            chan.send(self)
+        }
+    }
+}
+
+/// Finds a session by its join code.
+struct GetSession {
+    code: String,
+}
+
 impl Message for GetSession {
     type Result = Option<Addr<GameSession>>;
 }
@@ -139,53 +332,61 @@ impl Handler<GetSession> for SessionManager {
     type Result = Option<Addr<GameSession>>;
 
     fn handle(&mut self, msg: GetSession, _: &mut Self::Context) -> Self::Result {
-        self.sessions.get(&msg.id).cloned()
+        self.find(&msg.code)
     }
 }
 
 struct CreateSession {
     host_name: String,
+    public: bool,
+    preset: Option<String>,
+    rules: RulesUpdate,
 }
 
 impl Message for CreateSession {
-    type Result = CreateSessionRes;
+    type Result = Result<CreateSessionRes, MError>;
 }
 
 impl Handler<CreateSession> for SessionManager {
-    type Result = CreateSessionRes;
-
-    fn handle(&mut self, msg: CreateSession, _: &mut Self::Context) -> Self::Result {
+    type Result = Result<CreateSessionRes, MError>;
+
+    fn handle(&mut self, msg: CreateSession, ctx: &mut Self::Context) -> Self::Result {
+        let base = match &msg.preset {
+            Some(name) => self.presets.get(name)?,
+            None => Ruleset::default(),
+        };
+        let rules = base.apply(msg.rules);
+        rules.validate()?;
         let sess_id = gen_uuid();
+        let code = self.new_code();
         let host_secret = gen_uuid();
-        let session = GameSession::new(msg.host_name, host_secret.clone()).start();
-        debug!("adding session {} to sessions", sess_id);
+        let session = GameSession::new(
+            sess_id.clone(),
+            code.clone(),
+            rules,
+            msg.host_name,
+            host_secret.clone(),
+            msg.public,
+            self.env(ctx),
+        )
+        .start();
+        debug!("adding session {} to sessions as {}", sess_id, code);
         self.sessions.insert(sess_id.clone(), session);
-        CreateSessionRes {
-            session_id: sess_id,
+        self.codes.insert(code.clone(), sess_id);
+        Ok(CreateSessionRes {
+            code,
             secret: host_secret,
-        }
+        })
     }
 }
 
 struct CreateSessionRes {
-    session_id: String,
+    code: String,
     secret: String,
 }
 
-impl MessageResponse<SessionManager, CreateSession> for CreateSessionRes {
-    fn handle<R: ResponseChannel<CreateSession>>(
-        self,
-        _: &mut <SessionManager as Actor>::Context,
-        tx: Option<R>,
-    ) {
-        if let Some(chan) = tx {
-            chan.send(self)
-        }
-    }
-}
-
 struct JoinSession {
-    session_id: String,
+    code: String,
     name: String,
 }
 
@@ -197,9 +398,8 @@ impl Handler<JoinSession> for SessionManager {
     type Result = ResponseActFuture<Self, SessionJoined, MError>;
 
     fn handle(&mut self, msg: JoinSession, _: &mut Self::Context) -> Self::Result {
-        let session = self.sessions.iter().find(|(k, _)| *k == &msg.session_id);
-        let session_id = msg.session_id.clone();
-        if let Some((_, game)) = session {
+        let code = msg.code.to_uppercase();
+        if let Some(game) = self.find(&code) {
             let secret = gen_uuid();
             let create_user = game
                 .send(CreateUser {
@@ -208,7 +408,7 @@ impl Handler<JoinSession> for SessionManager {
                 })
                 .map_err(|_| MError::InternalError)
                 .and_then(|res| match res {
-                    Ok(_) => Ok(SessionJoined { session_id, secret }),
+                    Ok(_) => Ok(SessionJoined { code, secret }),
                     Err(e) => Err(e),
                 });
             Box::new(wrap_future::<_, Self>(create_user))
@@ -219,24 +419,216 @@ impl Handler<JoinSession> for SessionManager {
 }
 
 struct SessionJoined {
-    session_id: String,
+    code: String,
     secret: String,
 }
 
+/// What gets written to the store, the game plus the bits of the session
+/// the engine doesn't know about.
+#[derive(Serialize)]
+struct SavedSessionRef<'a> {
+    code: &'a str,
+    public: bool,
+    #[serde(flatten)]
+    game: &'a State<ConnectionAddr>,
+}
+
+#[derive(Deserialize)]
+struct SavedSession {
+    #[serde(default)]
+    code: Option<String>,
+    #[serde(default)]
+    public: bool,
+    #[serde(flatten)]
+    game: State<ConnectionAddr>,
+}
+
+/// What a session needs from the manager that created it.
+struct SessionEnv {
+    store: Arc<dyn Store>,
+    manager: Addr<SessionManager>,
+    timeouts: Timeouts,
+}
+
 struct GameSession {
+    id: String,
+    /// Short code players join with.
+    code: String,
     game: State<ConnectionAddr>,
+    /// Whether the game shows up in the lobby list.
+    public: bool,
+    env: SessionEnv,
+    /// When the last player disconnected.
+    idle_since: Option<DateTime<Utc>>,
+    /// When the host last disconnected.
+    host_away_since: Option<DateTime<Utc>>,
+    /// The deadline the phase timer is currently armed for.
+    timer: Option<(DateTime<Utc>, SpawnHandle)>,
 }
 
 impl GameSession {
-    fn new(host_name: String, host_secret: String) -> Self {
-        GameSession {
-            game: State::new(Ruleset::default(), host_name, host_secret),
+    fn new(
+        id: String,
+        code: String,
+        rules: Ruleset,
+        host_name: String,
+        host_secret: String,
+        public: bool,
+        env: SessionEnv,
+    ) -> Self {
+        let session = GameSession {
+            id,
+            code,
+            game: State::new(rules, host_name, host_secret),
+            public,
+            env,
+            idle_since: None,
+            host_away_since: None,
+            timer: None,
+        };
+        session.persist();
+        session
+    }
+
+    fn restore(id: String, code: String, saved: SavedSession, env: SessionEnv) -> Self {
+        let session = GameSession {
+            id,
+            code,
+            game: saved.game,
+            public: saved.public,
+            env,
+            idle_since: None,
+            host_away_since: None,
+            timer: None,
+        };
+        // the code may have been reissued
+        session.persist();
+        session
+    }
+
+    fn persist(&self) {
+        let saved = SavedSessionRef {
+            code: &self.code,
+            public: self.public,
+            game: &self.game,
+        };
+        let res = serde_json::to_string(&saved)
+            .map_err(std::io::Error::from)
+            .and_then(|data| self.env.store.save(&self.id, &data));
+        if let Err(e) = res {
+            error!("error saving session {}: {}", self.id, e);
+        }
+    }
+
+    /// Lists the game in the lobby browser while it's public and waiting
+    /// for players, and takes it off otherwise.
+    fn publish(&self) {
+        let summary = self.game.summary();
+        let listing = if self.public && summary.phase == PhaseName::Lobby {
+            Some(LobbyListing {
+                code: self.code.clone(),
+                summary,
+            })
+        } else {
+            None
+        };
+        self.env.manager.do_send(UpdateListing {
+            id: self.id.clone(),
+            listing,
+        });
+    }
+
+    /// Everything that needs doing after the game has changed.
+    fn changed(&mut self, ctx: &mut Context<Self>) {
+        if self.game.is_closed() {
+            self.teardown(ctx);
+            return;
+        }
+        self.persist();
+        self.publish();
+        self.arm_timer(ctx);
+    }
+
+    /// Hands hosting to someone else if the host has been gone too long,
+    /// and tears the session down once nobody has been connected for too
+    /// long. Finished games are kept longer so the recap can still be fetched.
+    fn sweep(&mut self, ctx: &mut Context<Self>) {
+        let now = Utc::now();
+        if self.game.host_connected() {
+            self.host_away_since = None;
+        } else {
+            let away_since = *self.host_away_since.get_or_insert(now);
+            if now - away_since >= self.env.timeouts.host {
+                if let Some(host) = self.game.migrate_host() {
+                    debug!("session {}: host moved to {}", self.id, host);
+                    self.host_away_since = None;
+                    self.changed(ctx);
+                }
+            }
+        }
+
+        if self.game.has_connections() {
+            self.idle_since = None;
+            return;
+        }
+        let idle_since = *self.idle_since.get_or_insert(now);
+        let timeout = if self.game.is_over() {
+            self.env.timeouts.recap
+        } else {
+            self.env.timeouts.idle
+        };
+        if now - idle_since >= timeout {
+            self.teardown(ctx);
+        }
+    }
+
+    fn teardown(&mut self, ctx: &mut Context<Self>) {
+        debug!("session {}: tearing down", self.id);
+        if let Err(e) = self.env.store.remove(&self.id) {
+            error!("error removing session {}: {}", self.id, e);
+        }
+        self.env.manager.do_send(RemoveSession {
+            id: self.id.clone(),
+        });
+        ctx.stop();
+    }
+
+    /// Makes sure the timer matches the game's current deadline, replacing it
+    /// when the phase has moved on early (e.g. a majority vote).
+    fn arm_timer(&mut self, ctx: &mut Context<Self>) {
+        let next = self.game.next_state_time();
+        if let Some((at, handle)) = self.timer.take() {
+            if Some(at) == next {
+                self.timer = Some((at, handle));
+                return;
+            }
+            debug!("session {}: cancelling timer for {}", self.id, at);
+            ctx.cancel_future(handle);
+        }
+        if let Some(at) = next {
+            debug!("session {}: arming timer for {}", self.id, at);
+            let delay = (at - Utc::now()).to_std().unwrap_or(Duration::from_secs(0));
+            let handle = ctx.run_later(delay, |act, ctx| {
+                act.timer = None;
+                if act.game.tick(Utc::now()) {
+                    act.changed(ctx);
+                } else {
+                    act.arm_timer(ctx);
+                }
+            });
+            self.timer = Some((at, handle));
         }
     }
 }
 
 impl Actor for GameSession {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.publish();
+        self.arm_timer(ctx);
+        ctx.run_interval(SWEEP_INTERVAL, |act, ctx| act.sweep(ctx));
+    }
 }
 
 struct GetPlayername {
@@ -267,8 +659,29 @@ impl Message for CreateUser {
 impl Handler<CreateUser> for GameSession {
     type Result = Result<(), MError>;
 
-    fn handle(&mut self, msg: CreateUser, _: &mut Self::Context) -> Self::Result {
-        self.game.create_user(msg.name, msg.secret)
+    fn handle(&mut self, msg: CreateUser, ctx: &mut Self::Context) -> Self::Result {
+        self.game.create_user(msg.name, msg.secret)?;
+        self.changed(ctx);
+        Ok(())
+    }
+}
+
+struct PlayerAction {
+    player_name: String,
+    action: Action,
+}
+
+impl Message for PlayerAction {
+    type Result = Result<(), MError>;
+}
+
+impl Handler<PlayerAction> for GameSession {
+    type Result = Result<(), MError>;
+
+    fn handle(&mut self, msg: PlayerAction, ctx: &mut Self::Context) -> Self::Result {
+        self.game.handle_action(msg.player_name, msg.action)?;
+        self.changed(ctx);
+        Ok(())
     }
 }
 
@@ -301,6 +714,22 @@ impl Handler<GetPlayerConnection> for GameSession {
     }
 }
 
+/// The recap of a finished game, along with its join code to name the
+/// download after.
+struct GetRecap;
+
+impl Message for GetRecap {
+    type Result = Result<(String, Recap), MError>;
+}
+
+impl Handler<GetRecap> for GameSession {
+    type Result = Result<(String, Recap), MError>;
+
+    fn handle(&mut self, _: GetRecap, _: &mut Self::Context) -> Self::Result {
+        Ok((self.code.clone(), self.game.recap()?))
+    }
+}
+
 #[derive(Clone)]
 struct Connection {
     id: String,
@@ -398,12 +827,46 @@ impl StreamHandler<ws::Message, ws::ProtocolError> for Connection {
     fn handle(&mut self, msg: ws::Message, ctx: &mut Self::Context) {
         match msg {
             ws::Message::Ping(msg) => ctx.pong(&msg),
-            ws::Message::Text(text) => ctx.text(text),
+            ws::Message::Text(text) => self.handle_text(text, ctx),
             _ => (),
         }
     }
 }
 
+impl Connection {
+    fn handle_text(&mut self, text: String, ctx: &mut <Self as Actor>::Context) {
+        let action = match serde_json::from_str::<Action>(&text) {
+            Ok(action) => action,
+            Err(e) => {
+                debug!("malformed action from {}: {}", self.name, e);
+                Self::send_error(MError::MalformedAction(e.to_string()), ctx);
+                return;
+            }
+        };
+        let apply = wrap_future::<_, Self>(
+            self.sess
+                .send(PlayerAction {
+                    player_name: self.name.clone(),
+                    action,
+                })
+                .map_err(|e| {
+                    error!("error sending action to game session: {}", e);
+                }),
+        );
+        let reply = apply.map(|res, act, ctx| {
+            if let Err(e) = res {
+                debug!("action from {} rejected: {}", act.name, e);
+                Self::send_error(e, ctx);
+            }
+        });
+        ctx.spawn(reply);
+    }
+
+    fn send_error(e: MError, ctx: &mut <Self as Actor>::Context) {
+        ctx.text(serde_json::to_string(&Response::from(e)).expect("error serializing response"));
+    }
+}
+
 #[derive(Message)]
 struct TerminateConnection;
 
@@ -454,67 +917,129 @@ impl PlayerConnection for ConnectionAddr {
     fn is_alive(&self) -> bool {
         self.0.upgrade().is_some()
     }
+
+    fn close(&self) {
+        if let Some(addr) = self.0.upgrade() {
+            addr.do_send(TerminateConnection)
+        }
+    }
 }
 
 #[derive(Deserialize)]
 struct CreateLobbyArgs {
     name: String,
+    /// Private games can only be joined by someone given the join code.
+    #[serde(default)]
+    private: bool,
+    /// Name of the ruleset to start from, the default rules if not given.
+    preset: Option<String>,
+    /// Changes from the preset.
+    #[serde(default)]
+    rules: RulesUpdate,
 }
 
 #[derive(Serialize)]
 struct CreateLobbyResp {
-    session_id: String,
+    code: String,
     secret: String,
 }
 
 fn create_lobby(
     data: web::Data<AppState>,
-    web::Query(info): web::Query<CreateLobbyArgs>,
+    web::Json(info): web::Json<CreateLobbyArgs>,
 ) -> impl Future<Item = HttpResponse, Error = Error> {
     data.sm
         .send(CreateSession {
             host_name: info.name,
+            public: !info.private,
+            preset: info.preset,
+            rules: info.rules,
         })
         .map_err(Error::from)
-        .and_then(|resp| {
-            Ok(HttpResponse::Ok().json(CreateLobbyResp {
-                session_id: resp.session_id,
+        .and_then(|resp| match resp {
+            Ok(resp) => Ok(HttpResponse::Ok().json(CreateLobbyResp {
+                code: resp.code,
                 secret: resp.secret,
-            }))
+            })),
+            Err(e) => Err(Error::from(ApiError(e))),
         })
 }
 
+fn list_lobbies(data: web::Data<AppState>) -> impl Future<Item = HttpResponse, Error = Error> {
+    data.sm
+        .send(ListLobbies)
+        .map_err(Error::from)
+        .map(|lobbies| HttpResponse::Ok().json(lobbies.0))
+}
+
 #[derive(Deserialize)]
 struct JoinLobbyArgs {
-    session_id: String,
+    code: String,
     name: String,
 }
 
 #[derive(Serialize)]
 struct JoinLobbyRes {
-    session_id: String,
+    code: String,
     secret: String,
 }
 
 fn join_lobby(
     data: web::Data<AppState>,
-    web::Query(info): web::Query<JoinLobbyArgs>,
+    web::Json(info): web::Json<JoinLobbyArgs>,
 ) -> impl Future<Item = HttpResponse, Error = Error> {
     data.sm
         .send(JoinSession {
-            session_id: info.session_id,
+            code: info.code,
             name: info.name,
         })
         .map_err(Error::from)
         .and_then(|resp| match resp {
             Ok(res) => Ok(HttpResponse::Ok().json(JoinLobbyRes {
-                session_id: res.session_id,
+                code: res.code,
                 secret: res.secret,
             })),
-            Err(e) => Err(Error::from(InternalError::new(
-                e,
-                StatusCode::from_u16(500).unwrap(),
-            ))),
+            Err(e) => Err(Error::from(ApiError(e))),
+        })
+}
+
+#[derive(Deserialize)]
+struct RecapArgs {
+    session: String,
+    format: Option<String>,
+}
+
+fn download_recap(
+    data: web::Data<AppState>,
+    web::Query(info): web::Query<RecapArgs>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    data.sm
+        .send(GetSession {
+            code: info.session.clone(),
+        })
+        .map_err(Error::from)
+        .and_then(move |sess| match sess {
+            Some(game_sess) => Ok(game_sess.send(GetRecap).map_err(Error::from)),
+            None => Err(Error::from(ApiError(MError::InvalidSession))),
+        })
+        .flatten()
+        .and_then(move |recap| match recap {
+            Ok((code, recap)) => Ok(match info.format.as_ref().map(String::as_str) {
+                Some("markdown") => HttpResponse::Ok()
+                    .content_type("text/markdown; charset=utf-8")
+                    .header(
+                        "Content-Disposition",
+                        format!("attachment; filename=\"{}.md\"", code),
+                    )
+                    .body(recap.to_markdown()),
+                _ => HttpResponse::Ok()
+                    .header(
+                        "Content-Disposition",
+                        format!("attachment; filename=\"{}.json\"", code),
+                    )
+                    .json(recap),
+            }),
+            Err(e) => Err(Error::from(ApiError(e))),
         })
 }
 
@@ -536,7 +1061,7 @@ fn connect_websocket(
     );
     data.sm
         .send(GetSession {
-            id: info.session.clone(),
+            code: info.session.clone(),
         })
         .map_err(|e| {
             error!("error getting session: {}", e);
@@ -547,10 +1072,7 @@ fn connect_websocket(
                 Some(game_sess) => game_sess,
                 None => {
                     error!("no session found: {}", info.session);
-                    return Err(Error::from(InternalError::new(
-                        MError::InvalidSession,
-                        StatusCode::from_u16(500).unwrap(),
-                    )));
+                    return Err(Error::from(ApiError(MError::InvalidSession)));
                 }
             };
 
@@ -572,15 +1094,20 @@ fn connect_websocket(
                     )},
                     None => {
                         error!("player secret not found: sess={} secret={}", info.session, info.secret);
-                        Err(Error::from(InternalError::new(
-                        MError::InvalidSecret,
-                        StatusCode::from_u16(500).unwrap(),
-                    )))},
+                        Err(Error::from(ApiError(MError::InvalidSecret)))},
                 }))
         })
         .flatten()
 }
 
+fn action_schema() -> HttpResponse {
+    HttpResponse::Ok().json(mafia::schema::action())
+}
+
+fn response_schema() -> HttpResponse {
+    HttpResponse::Ok().json(mafia::schema::response())
+}
+
 fn main() {
     std::env::set_var("RUST_LOG", "dev=debug,mafia=debug,actix_web=debug");
     std::env::set_var("RUST_BACKTRACE", "1");
@@ -589,9 +1116,19 @@ fn main() {
     HttpServer::new(|| {
         App::new()
             .data(AppState::default())
-            .route("/create", web::get().to_async(create_lobby))
-            .route("/join", web::get().to_async(join_lobby))
+            .route(
+                "/create",
+                web::post().data(json_config()).to_async(create_lobby),
+            )
+            .route(
+                "/join",
+                web::post().data(json_config()).to_async(join_lobby),
+            )
+            .route("/lobbies", web::get().to_async(list_lobbies))
             .route("/ws", web::get().to_async(connect_websocket))
+            .route("/recap", web::get().to_async(download_recap))
+            .route("/schema/action", web::get().to(action_schema))
+            .route("/schema/response", web::get().to(response_schema))
             .wrap(Logger::new("ip=%a code=%r req_mili=%D resp_size=%b"))
     })
     .bind("127.0.0.1:8088")
@@ -0,0 +1,64 @@
+use log::debug;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Somewhere to keep serialized games so they survive a server restart.
+pub trait Store: Send + Sync {
+    fn save(&self, id: &str, game: &str) -> io::Result<()>;
+    fn load_all(&self) -> io::Result<Vec<(String, String)>>;
+    fn remove(&self, id: &str) -> io::Result<()>;
+}
+
+/// Keeps one json file per session in a directory.
+pub struct FsStore {
+    dir: PathBuf,
+}
+
+impl FsStore {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        FsStore { dir: dir.into() }
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+}
+
+impl Store for FsStore {
+    fn save(&self, id: &str, game: &str) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        // write then rename so a crash mid-write never leaves a torn file
+        let tmp = self.dir.join(format!("{}.json.tmp", id));
+        fs::write(&tmp, game)?;
+        fs::rename(&tmp, self.path(id))
+    }
+
+    fn load_all(&self) -> io::Result<Vec<(String, String)>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut games = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension() != Some(OsStr::new("json")) {
+                continue;
+            }
+            let id = match path.file_stem().and_then(|s| s.to_str()) {
+                Some(id) => id.to_string(),
+                None => continue,
+            };
+            debug!("loading session {} from {}", id, path.display());
+            games.push((id, fs::read_to_string(&path)?));
+        }
+        Ok(games)
+    }
+
+    fn remove(&self, id: &str) -> io::Result<()> {
+        match fs::remove_file(self.path(id)) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            res => res,
+        }
+    }
+}
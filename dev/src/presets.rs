@@ -0,0 +1,69 @@
+use log::{debug, error};
+use mafia::{Error as MError, RulesUpdate, Ruleset, PRESETS};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+
+/// Rulesets a host can pick by name when creating a game.
+pub struct Presets {
+    presets: HashMap<String, Ruleset>,
+}
+
+impl Presets {
+    /// The engine's built-in presets, plus one for every `.toml` or `.json`
+    /// file in `dir` named after the file. Each file holds a partial ruleset
+    /// applied on top of the defaults, and can replace a built-in preset.
+    /// Files that can't be read or describe an unplayable game are skipped.
+    pub fn load<P: AsRef<Path>>(dir: P) -> Self {
+        let mut presets: HashMap<String, Ruleset> = PRESETS
+            .iter()
+            .filter_map(|name| Some((name.to_string(), Ruleset::preset(name)?)))
+            .collect();
+        let dir = dir.as_ref();
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                debug!("not loading presets from {}: {}", dir.display(), e);
+                return Presets { presets };
+            }
+        };
+        for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+            let name = match path.file_stem().and_then(|s| s.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            let text = match path.extension().and_then(OsStr::to_str) {
+                Some("toml") | Some("json") => fs::read_to_string(&path),
+                _ => continue,
+            };
+            let update = text.map_err(|e| e.to_string()).and_then(|text| {
+                if path.extension() == Some(OsStr::new("toml")) {
+                    toml::from_str::<RulesUpdate>(&text).map_err(|e| e.to_string())
+                } else {
+                    serde_json::from_str::<RulesUpdate>(&text).map_err(|e| e.to_string())
+                }
+            });
+            let rules = update.and_then(|update| {
+                let rules = Ruleset::default().apply(update);
+                rules.validate().map_err(|e| e.to_string())?;
+                Ok(rules)
+            });
+            match rules {
+                Ok(rules) => {
+                    debug!("loaded preset {} from {}", name, path.display());
+                    presets.insert(name, rules);
+                }
+                Err(e) => error!("error loading preset {}: {}", path.display(), e),
+            }
+        }
+        Presets { presets }
+    }
+
+    pub fn get(&self, name: &str) -> Result<Ruleset, MError> {
+        self.presets
+            .get(name)
+            .cloned()
+            .ok_or_else(|| MError::UnknownPreset(name.to_string()))
+    }
+}
@@ -1,18 +1,41 @@
+use crate::player::PlayerName;
+use crate::ruleset::RulesUpdate;
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+/// A request from a client, sent as a JSON object whose `type` field names
+/// the `ActionE` variant, e.g. `{"type": "Vote", "target": "alice"}`.
+#[derive(Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
 pub struct Action {
     #[serde(flatten)]
     pub a: ActionE,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
 #[serde(tag = "type")]
 pub enum ActionE {
     Message(ActionMessage),
+    Start,
+    Vote { target: PlayerName },
+    Unvote,
+    SkipVote,
+    Kill { target: PlayerName },
+    Protect { target: PlayerName },
+    Roleblock { target: PlayerName },
+    Investigate { target: PlayerName },
+    Whisper { to: PlayerName, text: String },
+    Kick { player: PlayerName },
+    Ban { player: PlayerName },
+    TransferHost { to: PlayerName },
+    CloseLobby,
+    UpdateRules { rules: RulesUpdate },
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
 pub struct ActionMessage {
     pub text: String,
 }
@@ -1,16 +1,39 @@
-use crate::player::PlayerName;
-use serde::Serialize;
+use crate::player::{PlayerName, Role};
+use crate::ruleset::RuleViolation;
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use std::error::Error as ErrorT;
 use std::fmt;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug, Serialize)]
+/// Sent as `{"error": <variant>, "detail": <fields>}`, with `detail` left out
+/// when there's nothing more to say.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(tag = "error", content = "detail")]
 pub enum Error {
     InvalidPlayerName(PlayerName),
     InvalidSession,
     InvalidSecret,
     PlayerNameTaken(PlayerName),
+    GameInProgress,
+    NotHost,
+    NotEnoughPlayers { required: usize, joined: usize },
+    TooManyRoles { roles: usize, players: usize },
+    WrongPhase,
+    PlayerDead(PlayerName),
+    TargetDead(PlayerName),
+    WrongRole(Role),
+    GameOver,
+    Muted,
+    InvalidHistory,
+    MalformedAction(String),
+    Banned(PlayerName),
+    LobbyClosed,
+    InvalidRules(Vec<RuleViolation>),
+    UnknownPreset(String),
     InternalError,
 }
 
@@ -22,6 +45,36 @@ impl fmt::Display for Error {
             InvalidSession => write!(f, "invalid session id"),
             InvalidSecret => write!(f, "invalid secret"),
             PlayerNameTaken(p) => write!(f, "name {} is already taken!", p),
+            GameInProgress => write!(f, "game has already started"),
+            NotHost => write!(f, "only the host can do that"),
+            NotEnoughPlayers { required, joined } => write!(
+                f,
+                "not enough players: {} required, {} joined",
+                required, joined
+            ),
+            TooManyRoles { roles, players } => write!(
+                f,
+                "ruleset hands out {} roles but only {} players joined",
+                roles, players
+            ),
+            WrongPhase => write!(f, "that can't be done right now"),
+            PlayerDead(p) => write!(f, "{} is dead", p),
+            TargetDead(p) => write!(f, "{} is already dead", p),
+            WrongRole(r) => write!(f, "only a {:?} can do that", r),
+            GameOver => write!(f, "the game is over"),
+            Muted => write!(f, "you can't speak right now"),
+            InvalidHistory => write!(f, "game history can't be replayed"),
+            MalformedAction(e) => write!(f, "malformed action: {}", e),
+            Banned(p) => write!(f, "{} has been banned from this game", p),
+            LobbyClosed => write!(f, "the lobby has been closed"),
+            InvalidRules(problems) => {
+                write!(f, "invalid rules")?;
+                for (i, p) in problems.iter().enumerate() {
+                    write!(f, "{} {}", if i == 0 { ":" } else { ";" }, p)?;
+                }
+                Ok(())
+            }
+            UnknownPreset(name) => write!(f, "there's no preset called {}", name),
             InternalError => write!(f, "internal error"),
         }
     }
@@ -0,0 +1,103 @@
+use crate::action::ActionE;
+use crate::player::{PlayerName, Role, Team};
+use crate::ruleset::Ruleset;
+use crate::view::PhaseName;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// One entry in a game's append-only history.
+///
+/// `Created`, `Joined`, `Action`, `Tick` and `HostMigrated` are the inputs that drive the
+/// game and are all `State::replay` needs. The rest are derived from them
+/// and recorded so the history can be read without re-running the game.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "event")]
+pub enum Event {
+    Created {
+        at: DateTime<Utc>,
+        rules: Ruleset,
+        host: PlayerName,
+        secret: String,
+        seed: u64,
+    },
+    Joined {
+        at: DateTime<Utc>,
+        player: PlayerName,
+        secret: String,
+    },
+    Action {
+        at: DateTime<Utc>,
+        player: PlayerName,
+        action: ActionE,
+    },
+    Tick {
+        at: DateTime<Utc>,
+    },
+    /// The host went missing and hosting was handed to someone still
+    /// connected.
+    HostMigrated {
+        at: DateTime<Utc>,
+        host: PlayerName,
+    },
+    PhaseChanged {
+        at: DateTime<Utc>,
+        day: u32,
+        phase: PhaseName,
+    },
+    RolesAssigned {
+        roles: BTreeMap<PlayerName, Role>,
+    },
+    VoteResolved {
+        day: u32,
+        /// target -> voters
+        tally: BTreeMap<PlayerName, Vec<PlayerName>>,
+        condemned: Option<PlayerName>,
+    },
+    NightResolved {
+        day: u32,
+        outcome: NightOutcome,
+    },
+    Died {
+        day: u32,
+        player: PlayerName,
+        role: Role,
+    },
+    GameEnded {
+        winner: Team,
+    },
+}
+
+impl Event {
+    /// Whether this event is an input to the game rather than something
+    /// derived from one.
+    pub fn is_input(&self) -> bool {
+        matches!(
+            self,
+            Event::Created { .. }
+                | Event::Joined { .. }
+                | Event::Action { .. }
+                | Event::Tick { .. }
+                | Event::HostMigrated { .. }
+        )
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct NightOutcome {
+    /// actor -> target for every action that was submitted
+    pub actions: BTreeMap<PlayerName, NightActionRecord>,
+    pub roleblocked: Vec<PlayerName>,
+    pub protected: Vec<PlayerName>,
+    /// Who the mafia went after, and whether they survived it.
+    pub attacked: Option<PlayerName>,
+    pub saved: bool,
+    /// detective -> (target, role learned)
+    pub investigations: BTreeMap<PlayerName, (PlayerName, Role)>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NightActionRecord {
+    pub role: Role,
+    pub target: PlayerName,
+}
@@ -1,15 +1,28 @@
 mod action;
 mod error;
+mod event;
 mod phases;
 mod player;
 mod player_connection;
+mod recap;
 mod response;
 mod ruleset;
+#[cfg(feature = "schema")]
+pub mod schema;
 mod state;
 mod util;
+mod view;
 
+pub use action::{Action, ActionE, ActionMessage};
 pub use error::{Error, Result};
+pub use event::{Event, NightActionRecord, NightOutcome};
+pub use player::{PlayerName, Role, Team};
 pub use player_connection::PlayerConnection;
-pub use response::Response;
-pub use ruleset::Ruleset;
+pub use recap::{DayRecap, DeathCause, DeathRecap, Recap};
+pub use response::{Channel, Response, ResponseE, PROTOCOL_VERSION};
+pub use ruleset::{
+    RoleCount, RoleDistribution, RoleRatio, RoleWeight, RuleViolation, RulesUpdate, Ruleset,
+    WhisperMode, WhisperRules, PRESETS,
+};
 pub use state::State;
+pub use view::{GameSummary, PhaseName, PlayerSummary, PlayerView};
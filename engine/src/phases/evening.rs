@@ -1,4 +1,53 @@
+use crate::action::ActionE;
+use crate::error::{Error, Result};
+use crate::phases::Night;
+use crate::player::{PlayerName, Role};
+use crate::player_connection::PlayerConnection;
+use crate::state::{Phase, RootState};
+use crate::util::{message_basic, message_mafia};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Evening {}
+
+impl Evening {
+    pub fn handle_action<PC>(
+        self,
+        root: RootState<PC>,
+        player: PlayerName,
+        act: ActionE,
+        _now: DateTime<Utc>,
+    ) -> Result<(Phase, RootState<PC>)>
+    where
+        PC: PlayerConnection,
+    {
+        match act {
+            ActionE::Message(m) => {
+                let sender = root.get_player(&player)?;
+                if sender.state.is_alive() && sender.role == Role::Mafioso {
+                    let spectate = root.rules.dead_spectate();
+                    message_mafia(&player, m, || root.players.iter(), spectate)?
+                } else {
+                    message_basic(&player, m, || root.players.iter())?
+                }
+            }
+            ActionE::Start => return Err(Error::GameInProgress),
+            _ => return Err(Error::WrongPhase),
+        }
+        Ok((self.into(), root))
+    }
+
+    pub fn next_phase<PC>(self, root: RootState<PC>, now: DateTime<Utc>) -> (Phase, RootState<PC>)
+    where
+        PC: PlayerConnection,
+    {
+        (
+            Night::new().into(),
+            RootState {
+                next_state_time: root.rules.night_end(now),
+                ..root
+            },
+        )
+    }
+}
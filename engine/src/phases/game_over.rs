@@ -0,0 +1,80 @@
+use crate::action::ActionE;
+use crate::error::{Error, Result};
+use crate::event::Event;
+use crate::player::{PlayerName, Role, Team};
+use crate::player_connection::PlayerConnection;
+use crate::response::Response;
+use crate::state::{Phase, RootState};
+use crate::util::{broadcast, message_basic};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GameOver {
+    pub winner: Team,
+    pub reveal: BTreeMap<PlayerName, Role>,
+}
+
+impl GameOver {
+    /// The town wins once every mafioso is dead, the mafia win once they are
+    /// at least as many as the rest of the living players.
+    pub fn check<PC>(root: &RootState<PC>) -> Option<Self>
+    where
+        PC: PlayerConnection,
+    {
+        let (mafia, town) = root
+            .living_players()
+            .partition::<Vec<_>, _>(|p| p.role.team() == Team::Mafia);
+        let winner = if mafia.is_empty() {
+            Team::Town
+        } else if mafia.len() >= town.len() {
+            Team::Mafia
+        } else {
+            return None;
+        };
+        let reveal = root
+            .players
+            .iter()
+            .map(|p| (p.get_name().to_string(), p.role))
+            .collect();
+        Some(GameOver { winner, reveal })
+    }
+
+    pub fn begin<PC>(self, mut root: RootState<PC>) -> (Phase, RootState<PC>)
+    where
+        PC: PlayerConnection,
+    {
+        root.events.push_back(Event::GameEnded {
+            winner: self.winner,
+        });
+        broadcast(
+            root.players.iter(),
+            Response::game_over(self.winner, self.reveal.clone()),
+        );
+        (
+            self.into(),
+            RootState {
+                next_state_time: None,
+                ..root
+            },
+        )
+    }
+
+    pub fn handle_action<PC>(
+        self,
+        root: RootState<PC>,
+        player: PlayerName,
+        act: ActionE,
+        _now: DateTime<Utc>,
+    ) -> Result<(Phase, RootState<PC>)>
+    where
+        PC: PlayerConnection,
+    {
+        match act {
+            ActionE::Message(m) => message_basic(&player, m, || root.players.iter())?,
+            _ => return Err(Error::GameOver),
+        }
+        Ok((self.into(), root))
+    }
+}
@@ -1,4 +1,66 @@
+use crate::action::ActionE;
+use crate::error::{Error, Result};
+use crate::phases::{Evening, GameOver};
+use crate::player::PlayerName;
+use crate::player_connection::PlayerConnection;
+use crate::state::{Phase, RootState};
+use crate::util::{message_basic, message_dead};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
-pub struct LastWords;
+/// The condemned player gets to speak one last time. Everyone else still
+/// alive is muted until the phase ends and the condemned player dies; the dead
+/// can keep talking among themselves.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LastWords {
+    pub condemned: PlayerName,
+}
+
+impl LastWords {
+    pub fn handle_action<PC>(
+        self,
+        root: RootState<PC>,
+        player: PlayerName,
+        act: ActionE,
+        _now: DateTime<Utc>,
+    ) -> Result<(Phase, RootState<PC>)>
+    where
+        PC: PlayerConnection,
+    {
+        match act {
+            ActionE::Message(m) => {
+                if !root.get_player(&player)?.state.is_alive() {
+                    message_dead(&player, m, || root.players.iter())?
+                } else if player != self.condemned {
+                    return Err(Error::Muted);
+                } else {
+                    message_basic(&player, m, || root.players.iter())?
+                }
+            }
+            ActionE::Start => return Err(Error::GameInProgress),
+            _ => return Err(Error::WrongPhase),
+        }
+        Ok((self.into(), root))
+    }
+
+    pub fn next_phase<PC>(
+        self,
+        mut root: RootState<PC>,
+        now: DateTime<Utc>,
+    ) -> (Phase, RootState<PC>)
+    where
+        PC: PlayerConnection,
+    {
+        root.kill(&self.condemned);
+        if let Some(over) = GameOver::check(&root) {
+            return over.begin(root);
+        }
+        (
+            Evening {}.into(),
+            RootState {
+                next_state_time: root.rules.evening_end(now),
+                ..root
+            },
+        )
+    }
+}
@@ -1,4 +1,121 @@
+use crate::action::ActionE;
+use crate::error::{Error, Result};
+use crate::event::Event;
+use crate::phases::{GameOver, Morning};
+use crate::player::{Player, PlayerName, PlayerState};
+use crate::player_connection::PlayerConnection;
+use crate::response::Response;
+use crate::state::{Phase, RootState};
+use crate::util::{broadcast, message_basic};
+use chrono::{DateTime, Utc};
+use im::Vector;
+use log::debug;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Lobby {}
+
+impl Lobby {
+    pub fn handle_action<PC>(
+        self,
+        mut root: RootState<PC>,
+        player: PlayerName,
+        act: ActionE,
+        now: DateTime<Utc>,
+    ) -> Result<(Phase, RootState<PC>)>
+    where
+        PC: PlayerConnection,
+    {
+        match act {
+            ActionE::Message(m) => message_basic(&player, m, || root.players.iter())?,
+            ActionE::Kick { player: target } => {
+                root.require_host(&player)?;
+                root.remove_player(target, false)?;
+            }
+            ActionE::Ban { player: target } => {
+                root.require_host(&player)?;
+                root.remove_player(target, true)?;
+            }
+            ActionE::CloseLobby => {
+                root.require_host(&player)?;
+                root.close();
+            }
+            ActionE::UpdateRules { rules } => {
+                root.require_host(&player)?;
+                let rules = root.rules.apply(rules);
+                rules.validate()?;
+                root.rules = rules;
+                broadcast(
+                    root.players.iter(),
+                    Response::rules_changed(root.rules.clone()),
+                );
+            }
+            ActionE::Start => {
+                root.require_host(&player)?;
+                let required = root.rules.min_players();
+                let joined = root.players.len();
+                if joined < required {
+                    return Err(Error::NotEnoughPlayers { required, joined });
+                }
+                // the rules were only checked for `min_players`
+                let problems = root.rules.roles().check(joined);
+                if !problems.is_empty() {
+                    return Err(Error::InvalidRules(problems));
+                }
+                return self.start(root, now);
+            }
+            _ => return Err(Error::WrongPhase),
+        }
+        Ok((self.into(), root))
+    }
+
+    fn start<PC>(self, root: RootState<PC>, now: DateTime<Utc>) -> Result<(Phase, RootState<PC>)>
+    where
+        PC: PlayerConnection,
+    {
+        let mut rng = Pcg64::seed_from_u64(root.seed);
+        let mut roles = root.rules.roles().deal(root.players.len(), &mut rng)?;
+        roles.shuffle(&mut rng);
+
+        let players: Vector<Player<PC>> = root
+            .players
+            .iter()
+            .cloned()
+            .zip(roles)
+            .map(|(mut p, role)| {
+                p.role = role;
+                p.state = PlayerState::Alive;
+                p
+            })
+            .collect();
+        for p in players.iter() {
+            debug!("assigned role {:?} to {}", p.role, p.get_name());
+            if let Some(conn) = &p.connection {
+                conn.send(Response::role_assigned(p.role));
+            }
+        }
+
+        let roles = players
+            .iter()
+            .map(|p| (p.get_name().to_string(), p.role))
+            .collect();
+        let mut events = root.events;
+        events.push_back(Event::RolesAssigned { roles });
+
+        let root = RootState {
+            day: 1,
+            players,
+            events,
+            next_state_time: root.rules.morning_end(now),
+            ..root
+        };
+        // A deal with no town or no mafia is already decided.
+        if let Some(over) = GameOver::check(&root) {
+            return Ok(over.begin(root));
+        }
+        Ok((Morning {}.into(), root))
+    }
+}
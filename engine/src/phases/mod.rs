@@ -1,4 +1,5 @@
 mod evening;
+mod game_over;
 mod last_words;
 mod lobby;
 mod morning;
@@ -6,6 +7,7 @@ mod night;
 mod vote;
 
 pub use evening::Evening;
+pub use game_over::GameOver;
 pub use last_words::LastWords;
 pub use lobby::Lobby;
 pub use morning::Morning;
@@ -1,40 +1,50 @@
 use crate::action::ActionE;
-use crate::error::Result;
+use crate::error::{Error, Result};
+use crate::phases::Vote;
 use crate::player::PlayerName;
 use crate::player_connection::PlayerConnection;
-use crate::state::RootState;
+use crate::state::{Phase, RootState};
 use crate::util::message_basic;
+use chrono::{DateTime, Utc};
 use im::HashSet;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Morning {}
 
 impl Morning {
     pub fn handle_action<PC>(
         self,
-        root: RootState<PC>,
+        mut root: RootState<PC>,
         player: PlayerName,
         act: ActionE,
-    ) -> Result<(Self, RootState<PC>)>
+        now: DateTime<Utc>,
+    ) -> Result<(Phase, RootState<PC>)>
     where
         PC: PlayerConnection,
     {
         match act {
             ActionE::Message(m) => message_basic(&player, m, || root.players.iter())?,
+            ActionE::Start => return Err(Error::GameInProgress),
+            ActionE::SkipVote => {
+                if root.skip_vote(player)? {
+                    return Ok(self.next_phase(root, now));
+                }
+            }
+            _ => return Err(Error::WrongPhase),
         }
-        Ok((self, root))
+        Ok((self.into(), root))
     }
 
-    pub fn next_phase<PC>(self, root: RootState<PC>) -> (Self, RootState<PC>)
+    pub fn next_phase<PC>(self, root: RootState<PC>, now: DateTime<Utc>) -> (Phase, RootState<PC>)
     where
         PC: PlayerConnection,
     {
         (
-            self,
+            Vote::new().into(),
             RootState {
                 vote_skip: HashSet::new(),
-                next_state_time: root.rules.vote_end(),
+                next_state_time: root.rules.vote_end(now),
                 ..root
             },
         )
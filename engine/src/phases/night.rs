@@ -1,4 +1,206 @@
+use crate::action::ActionE;
+use crate::error::{Error, Result};
+use crate::event::{Event, NightActionRecord, NightOutcome};
+use crate::phases::{GameOver, Morning};
+use crate::player::{PlayerName, Role};
+use crate::player_connection::PlayerConnection;
+use crate::response::Response;
+use crate::state::{Phase, RootState};
+use crate::util::{message_basic, message_mafia, send_spectators};
+use chrono::{DateTime, Utc};
+use im::HashMap;
+use log::debug;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 
-#[derive(Serialize, Deserialize)]
-pub struct Night {}
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Night {
+    /// actor -> target, what the action does depends on the actor's role
+    pub actions: HashMap<PlayerName, PlayerName>,
+}
+
+impl Night {
+    pub fn new() -> Self {
+        Night {
+            actions: HashMap::new(),
+        }
+    }
+
+    pub fn handle_action<PC>(
+        self,
+        root: RootState<PC>,
+        player: PlayerName,
+        act: ActionE,
+        _now: DateTime<Utc>,
+    ) -> Result<(Phase, RootState<PC>)>
+    where
+        PC: PlayerConnection,
+    {
+        let (role, target) = match act {
+            ActionE::Message(m) => {
+                let sender = root.get_player(&player)?;
+                if sender.state.is_alive() && sender.role == Role::Mafioso {
+                    let spectate = root.rules.dead_spectate();
+                    message_mafia(&player, m, || root.players.iter(), spectate)?;
+                } else {
+                    message_basic(&player, m, || root.players.iter())?;
+                }
+                return Ok((self.into(), root));
+            }
+            ActionE::Start => return Err(Error::GameInProgress),
+            ActionE::Kill { target } => (Role::Mafioso, target),
+            ActionE::Protect { target } => (Role::Doctor, target),
+            ActionE::Roleblock { target } => (Role::Bartender, target),
+            ActionE::Investigate { target } => (Role::Detective, target),
+            _ => return Err(Error::WrongPhase),
+        };
+
+        let actor = root.get_player(&player)?;
+        if !actor.state.is_alive() {
+            return Err(Error::PlayerDead(player));
+        }
+        if actor.role != role {
+            return Err(Error::WrongRole(role));
+        }
+        if !root.get_player(&target)?.state.is_alive() {
+            return Err(Error::TargetDead(target));
+        }
+        debug!("night action: {} ({:?}) -> {}", player, role, target);
+        if root.rules.dead_spectate() {
+            send_spectators(
+                root.players.iter(),
+                Response::night_action(player.clone(), role, target.clone()),
+            );
+        }
+        let night = Night {
+            actions: self.actions.update(player, target),
+        };
+        Ok((night.into(), root))
+    }
+
+    pub fn next_phase<PC>(
+        self,
+        mut root: RootState<PC>,
+        now: DateTime<Utc>,
+    ) -> (Phase, RootState<PC>)
+    where
+        PC: PlayerConnection,
+    {
+        let outcome = self.resolve(&root);
+        root.events.push_back(Event::NightResolved {
+            day: root.day,
+            outcome: outcome.clone(),
+        });
+        if let (Some(dead), false) = (&outcome.attacked, outcome.saved) {
+            root.kill(dead);
+        }
+        if let Some(over) = GameOver::check(&root) {
+            return over.begin(root);
+        }
+        (
+            Morning {}.into(),
+            RootState {
+                day: root.day + 1,
+                next_state_time: root.rules.morning_end(now),
+                ..root
+            },
+        )
+    }
+
+    /// Resolves the night's actions in a fixed order.
+    ///
+    /// 1. Bartenders roleblock their targets. Roleblocks can't be blocked, so
+    ///    a bartender targeting another bartender does nothing. Any other
+    ///    blocked player's own action is dropped (they are told so).
+    /// 2. Doctors that weren't blocked protect their targets.
+    /// 3. The mafia kill the player most of them chose, ties going to the
+    ///    first name alphabetically. A protected target survives.
+    /// 4. Detectives that weren't blocked learn their target's role.
+    fn resolve<PC>(&self, root: &RootState<PC>) -> NightOutcome
+    where
+        PC: PlayerConnection,
+    {
+        let by_role = |role: Role| {
+            let mut acts: Vec<(&PlayerName, &PlayerName)> = self
+                .actions
+                .iter()
+                .filter(|(actor, _)| {
+                    root.get_player(actor)
+                        .map(|p| p.role == role)
+                        .unwrap_or(false)
+                })
+                .collect();
+            acts.sort();
+            acts
+        };
+        let send = |name: &PlayerName, resp: Response| {
+            if let Ok(Some(conn)) = root.get_player(name).map(|p| p.connection.as_ref()) {
+                conn.send(resp);
+            }
+        };
+
+        let blocked: BTreeSet<&PlayerName> = by_role(Role::Bartender)
+            .into_iter()
+            .map(|(_, t)| t)
+            .filter(|t| {
+                root.get_player(t)
+                    .map(|p| p.role != Role::Bartender)
+                    .unwrap_or(true)
+            })
+            .collect();
+        for actor in self.actions.keys().filter(|a| blocked.contains(a)) {
+            send(actor, Response::roleblocked());
+        }
+        let unblocked = |role: Role| {
+            by_role(role)
+                .into_iter()
+                .filter(|(a, _)| !blocked.contains(a))
+        };
+
+        let protected: BTreeSet<&PlayerName> = unblocked(Role::Doctor).map(|(_, t)| t).collect();
+
+        let mut kill_votes: BTreeMap<&PlayerName, usize> = BTreeMap::new();
+        for (_, target) in unblocked(Role::Mafioso) {
+            *kill_votes.entry(target).or_default() += 1;
+        }
+        let most = kill_votes.values().cloned().max().unwrap_or(0);
+        let attacked = kill_votes
+            .iter()
+            .find(|(_, &n)| n == most)
+            .map(|(&target, _)| target.clone());
+        let saved = match &attacked {
+            Some(target) if protected.contains(target) => {
+                debug!("{} was attacked but protected", target);
+                send(target, Response::saved());
+                true
+            }
+            _ => false,
+        };
+
+        let mut investigations = BTreeMap::new();
+        for (detective, target) in unblocked(Role::Detective) {
+            if let Ok(p) = root.get_player(target) {
+                send(detective, Response::investigation(target.clone(), p.role));
+                investigations.insert(detective.clone(), (target.clone(), p.role));
+            }
+        }
+
+        let actions = self
+            .actions
+            .iter()
+            .filter_map(|(actor, target)| {
+                let role = root.get_player(actor).ok()?.role;
+                let target = target.clone();
+                Some((actor.clone(), NightActionRecord { role, target }))
+            })
+            .collect();
+        NightOutcome {
+            actions,
+            roleblocked: blocked.into_iter().cloned().collect(),
+            protected: protected.into_iter().cloned().collect(),
+            attacked,
+            saved,
+            investigations,
+        }
+    }
+}
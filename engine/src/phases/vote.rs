@@ -1,4 +1,163 @@
+use crate::action::ActionE;
+use crate::error::{Error, Result};
+use crate::event::Event;
+use crate::phases::{Evening, LastWords};
+use crate::player::PlayerName;
+use crate::player_connection::PlayerConnection;
+use crate::response::Response;
+use crate::state::{Phase, RootState};
+use crate::util::{broadcast, message_basic};
+use chrono::{DateTime, Utc};
+use im::{HashMap, HashSet};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Serialize, Deserialize)]
-pub struct Vote {}
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Vote {
+    /// voter -> target
+    pub votes: HashMap<PlayerName, PlayerName>,
+}
+
+impl Vote {
+    pub fn new() -> Self {
+        Vote {
+            votes: HashMap::new(),
+        }
+    }
+
+    pub fn handle_action<PC>(
+        self,
+        mut root: RootState<PC>,
+        player: PlayerName,
+        act: ActionE,
+        now: DateTime<Utc>,
+    ) -> Result<(Phase, RootState<PC>)>
+    where
+        PC: PlayerConnection,
+    {
+        match act {
+            ActionE::Message(m) => message_basic(&player, m, || root.players.iter())?,
+            ActionE::Start => return Err(Error::GameInProgress),
+            ActionE::Vote { target } => {
+                if !root.get_player(&player)?.state.is_alive() {
+                    return Err(Error::PlayerDead(player));
+                }
+                if !root.get_player(&target)?.state.is_alive() {
+                    return Err(Error::TargetDead(target));
+                }
+                root.vote_skip.remove(&player);
+                let vote = Vote {
+                    votes: self.votes.update(player, target),
+                };
+                vote.send_tally(&root);
+                if let Some(condemned) = vote.majority(&root) {
+                    return Ok(vote.condemn(root, condemned, now));
+                }
+                return Ok((vote.into(), root));
+            }
+            ActionE::Unvote => {
+                if !root.get_player(&player)?.state.is_alive() {
+                    return Err(Error::PlayerDead(player));
+                }
+                let vote = Vote {
+                    votes: self.votes.without(&player),
+                };
+                vote.send_tally(&root);
+                return Ok((vote.into(), root));
+            }
+            ActionE::SkipVote => {
+                let vote = Vote {
+                    votes: self.votes.without(&player),
+                };
+                let skip = root.skip_vote(player)?;
+                vote.send_tally(&root);
+                if skip {
+                    return Ok(vote.no_lynch(root, now));
+                }
+                return Ok((vote.into(), root));
+            }
+            _ => return Err(Error::WrongPhase),
+        }
+        Ok((self.into(), root))
+    }
+
+    pub fn next_phase<PC>(self, root: RootState<PC>, now: DateTime<Utc>) -> (Phase, RootState<PC>)
+    where
+        PC: PlayerConnection,
+    {
+        let tally = self.tally();
+        let most = tally.values().map(Vec::len).max().unwrap_or(0);
+        let mut leaders = tally.into_iter().filter(|(_, v)| v.len() == most);
+        match (leaders.next(), leaders.next()) {
+            (Some((condemned, _)), None) => self.condemn(root, condemned, now),
+            _ => self.no_lynch(root, now),
+        }
+    }
+
+    /// target -> voters, ordered by name so clients get a stable listing.
+    pub fn tally(&self) -> BTreeMap<PlayerName, Vec<PlayerName>> {
+        let mut tally: BTreeMap<PlayerName, Vec<PlayerName>> = BTreeMap::new();
+        for (voter, target) in self.votes.iter() {
+            tally.entry(target.clone()).or_default().push(voter.clone());
+        }
+        for voters in tally.values_mut() {
+            voters.sort();
+        }
+        tally
+    }
+
+    fn send_tally<PC: PlayerConnection>(&self, root: &RootState<PC>) {
+        broadcast(root.players.iter(), Response::vote_tally(self.tally()));
+    }
+
+    fn majority<PC: PlayerConnection>(&self, root: &RootState<PC>) -> Option<PlayerName> {
+        let living = root.living_players().count();
+        self.tally()
+            .into_iter()
+            .find(|(_, voters)| voters.len() * 2 > living)
+            .map(|(target, _)| target)
+    }
+
+    fn condemn<PC>(
+        &self,
+        mut root: RootState<PC>,
+        condemned: PlayerName,
+        now: DateTime<Utc>,
+    ) -> (Phase, RootState<PC>)
+    where
+        PC: PlayerConnection,
+    {
+        root.events.push_back(Event::VoteResolved {
+            day: root.day,
+            tally: self.tally(),
+            condemned: Some(condemned.clone()),
+        });
+        (
+            LastWords { condemned }.into(),
+            RootState {
+                vote_skip: HashSet::new(),
+                next_state_time: root.rules.last_words_end(now),
+                ..root
+            },
+        )
+    }
+
+    fn no_lynch<PC>(&self, mut root: RootState<PC>, now: DateTime<Utc>) -> (Phase, RootState<PC>)
+    where
+        PC: PlayerConnection,
+    {
+        root.events.push_back(Event::VoteResolved {
+            day: root.day,
+            tally: self.tally(),
+            condemned: None,
+        });
+        (
+            Evening {}.into(),
+            RootState {
+                vote_skip: HashSet::new(),
+                next_state_time: root.rules.evening_end(now),
+                ..root
+            },
+        )
+    }
+}
@@ -1,12 +1,18 @@
 use crate::player_connection::PlayerConnection;
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 pub type PlayerName = String;
 pub type PlayerNameRef<'a> = &'a str;
 
 #[derive(Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
 pub struct Player<PC: PlayerConnection> {
     name: PlayerName,
+    /// Live connections can't be saved, a restored game starts with everyone
+    /// disconnected.
+    #[serde(skip)]
     pub connection: Option<PC>,
     pub state: PlayerState,
     pub role: Role,
@@ -41,7 +47,8 @@ impl PlayerState {
     }
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
 pub enum Role {
     Townie,
     Mafioso,
@@ -49,3 +56,19 @@ pub enum Role {
     Bartender,
     Detective,
 }
+
+impl Role {
+    pub fn team(self) -> Team {
+        match self {
+            Role::Mafioso => Team::Mafia,
+            Role::Townie | Role::Doctor | Role::Bartender | Role::Detective => Team::Town,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub enum Team {
+    Town,
+    Mafia,
+}
@@ -3,4 +3,6 @@ use crate::response::Response;
 pub trait PlayerConnection: Clone {
     fn send(&self, r: Response);
     fn is_alive(&self) -> bool;
+    /// Disconnects the player, e.g. after they've been kicked.
+    fn close(&self);
 }
@@ -0,0 +1,169 @@
+use crate::event::{Event, NightOutcome};
+use crate::player::{PlayerName, Role, Team};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// A summary of a finished game built from its event log.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Recap {
+    pub roles: BTreeMap<PlayerName, Role>,
+    pub days: Vec<DayRecap>,
+    /// In the order they happened.
+    pub deaths: Vec<DeathRecap>,
+    pub winner: Option<Team>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DayRecap {
+    pub day: u32,
+    /// target -> voters
+    pub votes: BTreeMap<PlayerName, Vec<PlayerName>>,
+    pub condemned: Option<PlayerName>,
+    pub night: Option<NightOutcome>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeathRecap {
+    pub day: u32,
+    pub player: PlayerName,
+    pub role: Role,
+    pub cause: DeathCause,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub enum DeathCause {
+    Lynched,
+    Killed,
+}
+
+impl Recap {
+    pub fn from_events<'a, I>(events: I) -> Self
+    where
+        I: IntoIterator<Item = &'a Event>,
+    {
+        let mut recap = Recap::default();
+        let mut cause = DeathCause::Lynched;
+        for event in events {
+            match event {
+                Event::RolesAssigned { roles } => recap.roles = roles.clone(),
+                Event::VoteResolved {
+                    day,
+                    tally,
+                    condemned,
+                } => {
+                    let d = recap.day(*day);
+                    d.votes = tally.clone();
+                    d.condemned = condemned.clone();
+                    cause = DeathCause::Lynched;
+                }
+                Event::NightResolved { day, outcome } => {
+                    recap.day(*day).night = Some(outcome.clone());
+                    cause = DeathCause::Killed;
+                }
+                Event::Died { day, player, role } => recap.deaths.push(DeathRecap {
+                    day: *day,
+                    player: player.clone(),
+                    role: *role,
+                    cause,
+                }),
+                Event::GameEnded { winner } => recap.winner = Some(*winner),
+                _ => (),
+            }
+        }
+        recap
+    }
+
+    fn day(&mut self, day: u32) -> &mut DayRecap {
+        if let Some(i) = self.days.iter().position(|d| d.day == day) {
+            return &mut self.days[i];
+        }
+        self.days.push(DayRecap {
+            day,
+            ..DayRecap::default()
+        });
+        self.days.last_mut().unwrap()
+    }
+
+    /// A human readable transcript of the game.
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        // writing to a String can't fail
+        let _ = self.write_markdown(&mut out);
+        out
+    }
+
+    fn write_markdown(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(out, "# Game recap")?;
+        writeln!(out)?;
+        match self.winner {
+            Some(winner) => writeln!(out, "**Winner:** {:?}", winner)?,
+            None => writeln!(out, "**Winner:** none yet")?,
+        }
+        writeln!(out)?;
+
+        writeln!(out, "## Roles")?;
+        writeln!(out)?;
+        for (player, role) in self.roles.iter() {
+            writeln!(out, "- {}: {:?}", player, role)?;
+        }
+
+        for day in self.days.iter() {
+            writeln!(out)?;
+            writeln!(out, "## Day {}", day.day)?;
+            writeln!(out)?;
+            if day.votes.is_empty() {
+                writeln!(out, "No votes were cast.")?;
+            }
+            for (target, voters) in day.votes.iter() {
+                writeln!(
+                    out,
+                    "- {} ({}): {}",
+                    target,
+                    voters.len(),
+                    voters.join(", ")
+                )?;
+            }
+            match &day.condemned {
+                Some(p) => writeln!(out, "\n{} was lynched.", p)?,
+                None => writeln!(out, "\nNobody was lynched.")?,
+            }
+
+            if let Some(night) = &day.night {
+                writeln!(out)?;
+                writeln!(out, "### Night {}", day.day)?;
+                writeln!(out)?;
+                for (actor, act) in night.actions.iter() {
+                    writeln!(out, "- {} ({:?}) targeted {}", actor, act.role, act.target)?;
+                }
+                for p in night.roleblocked.iter() {
+                    writeln!(out, "- {} was roleblocked", p)?;
+                }
+                for (detective, (target, role)) in night.investigations.iter() {
+                    writeln!(out, "- {} found out {} is a {:?}", detective, target, role)?;
+                }
+                match (&night.attacked, night.saved) {
+                    (Some(p), true) => writeln!(out, "\n{} was attacked but saved.", p)?,
+                    (Some(p), false) => writeln!(out, "\n{} was killed.", p)?,
+                    (None, _) => writeln!(out, "\nNobody was attacked.")?,
+                }
+            }
+        }
+
+        writeln!(out)?;
+        writeln!(out, "## Deaths")?;
+        writeln!(out)?;
+        for death in self.deaths.iter() {
+            let how = match death.cause {
+                DeathCause::Lynched => "lynched",
+                DeathCause::Killed => "killed at night",
+            };
+            writeln!(
+                out,
+                "- Day {}: {} ({:?}), {}",
+                death.day, death.player, death.role, how
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -1,17 +1,151 @@
 use crate::error::Error;
-use crate::player::PlayerName;
-use serde::Serialize;
+use crate::player::{PlayerName, Role, Team};
+use crate::ruleset::Ruleset;
+use crate::view::{PhaseName, PlayerView};
+use chrono::{DateTime, Utc};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Serialize)]
+/// Bumped whenever a change to `Action` or `Response` would break existing
+/// clients. Variant names are part of the protocol and must not be renamed.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// A message from the server, sent as a JSON object whose `type` field names
+/// the `ResponseE` variant.
+#[derive(Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
 pub struct Response {
     #[serde(flatten)]
-    resp: ResponseE,
+    pub resp: ResponseE,
 }
 
 impl Response {
-    pub fn message(src: Option<PlayerName>, text: String) -> Self {
+    pub fn hello(player: PlayerName) -> Self {
+        Response {
+            resp: ResponseE::Hello {
+                protocol_version: PROTOCOL_VERSION,
+                player,
+            },
+        }
+    }
+
+    pub fn snapshot(view: PlayerView) -> Self {
+        Response {
+            resp: ResponseE::Snapshot(view),
+        }
+    }
+
+    pub fn phase_changed(
+        day: u32,
+        phase: PhaseName,
+        next_state_time: Option<DateTime<Utc>>,
+    ) -> Self {
+        Response {
+            resp: ResponseE::PhaseChanged {
+                day,
+                phase,
+                next_state_time,
+            },
+        }
+    }
+
+    pub fn message(src: Option<PlayerName>, text: String, channel: Channel) -> Self {
+        Response {
+            resp: ResponseE::Message {
+                from: src,
+                text,
+                channel,
+            },
+        }
+    }
+
+    pub fn role_assigned(role: Role) -> Self {
+        Response {
+            resp: ResponseE::RoleAssigned { role },
+        }
+    }
+
+    pub fn vote_tally(tally: BTreeMap<PlayerName, Vec<PlayerName>>) -> Self {
+        Response {
+            resp: ResponseE::VoteTally { tally },
+        }
+    }
+
+    pub fn skip_votes(count: usize, required: usize) -> Self {
+        Response {
+            resp: ResponseE::SkipVotes { count, required },
+        }
+    }
+
+    pub fn roleblocked() -> Self {
+        Response {
+            resp: ResponseE::Roleblocked,
+        }
+    }
+
+    pub fn saved() -> Self {
+        Response {
+            resp: ResponseE::Saved,
+        }
+    }
+
+    pub fn investigation(target: PlayerName, role: Role) -> Self {
+        Response {
+            resp: ResponseE::Investigation { target, role },
+        }
+    }
+
+    pub fn whisper_notice(from: PlayerName, to: PlayerName) -> Self {
+        Response {
+            resp: ResponseE::WhisperNotice { from, to },
+        }
+    }
+
+    pub fn night_action(actor: PlayerName, role: Role, target: PlayerName) -> Self {
+        Response {
+            resp: ResponseE::NightAction {
+                actor,
+                role,
+                target,
+            },
+        }
+    }
+
+    pub fn death(player: PlayerName, role: Option<Role>) -> Self {
+        Response {
+            resp: ResponseE::Death { player, role },
+        }
+    }
+
+    pub fn kicked(player: PlayerName, banned: bool) -> Self {
+        Response {
+            resp: ResponseE::Kicked { player, banned },
+        }
+    }
+
+    pub fn host_changed(host: PlayerName) -> Self {
+        Response {
+            resp: ResponseE::HostChanged { host },
+        }
+    }
+
+    pub fn rules_changed(rules: Ruleset) -> Self {
+        Response {
+            resp: ResponseE::RulesChanged { rules },
+        }
+    }
+
+    pub fn lobby_closed() -> Self {
+        Response {
+            resp: ResponseE::LobbyClosed,
+        }
+    }
+
+    pub fn game_over(winner: Team, reveal: BTreeMap<PlayerName, Role>) -> Self {
         Response {
-            resp: ResponseE::Message { from: src, text },
+            resp: ResponseE::GameOver { winner, reveal },
         }
     }
 }
@@ -22,12 +156,72 @@ impl From<Error> for Response {
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
 #[serde(tag = "type")]
 pub enum ResponseE {
+    /// Sent first on every new connection.
+    Hello {
+        protocol_version: u32,
+        player: PlayerName,
+    },
+    /// The player's view of the whole game, sent after `Hello`.
+    Snapshot(PlayerView),
+    PhaseChanged {
+        day: u32,
+        phase: PhaseName,
+        next_state_time: Option<DateTime<Utc>>,
+    },
     Message {
         from: Option<PlayerName>,
         text: String,
+        channel: Channel,
+    },
+    RoleAssigned {
+        role: Role,
+    },
+    VoteTally {
+        tally: BTreeMap<PlayerName, Vec<PlayerName>>,
+    },
+    SkipVotes {
+        count: usize,
+        required: usize,
+    },
+    WhisperNotice {
+        from: PlayerName,
+        to: PlayerName,
+    },
+    NightAction {
+        actor: PlayerName,
+        role: Role,
+        target: PlayerName,
+    },
+    Roleblocked,
+    Saved,
+    Investigation {
+        target: PlayerName,
+        role: Role,
+    },
+    Death {
+        player: PlayerName,
+        role: Option<Role>,
+    },
+    GameOver {
+        winner: Team,
+        reveal: BTreeMap<PlayerName, Role>,
+    },
+    Kicked {
+        player: PlayerName,
+        banned: bool,
+    },
+    HostChanged {
+        host: PlayerName,
+    },
+    LobbyClosed,
+    /// The rules the game will be played with, sent whenever the host
+    /// changes them.
+    RulesChanged {
+        rules: Ruleset,
     },
     Error(Error),
 }
@@ -37,3 +231,13 @@ impl From<Error> for ResponseE {
         ResponseE::Error(e)
     }
 }
+
+/// Which conversation a message belongs to, so clients can show them apart.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, PartialEq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub enum Channel {
+    Town,
+    Mafia,
+    Dead,
+    Whisper,
+}
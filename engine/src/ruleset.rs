@@ -1,43 +1,666 @@
-use crate::phases::Morning;
+use crate::error::{Error, Result};
+use crate::phases::Lobby;
+use crate::player::Role;
+use crate::player::Team;
 use crate::state::Phase;
-use crate::util::{de_opt_dur, se_opt_dur};
+#[cfg(feature = "schema")]
+use crate::util::OptSecsSchema;
+use crate::util::{de_opt_dur, de_patch_secs, se_opt_dur};
+use crate::view::PhaseName;
 use chrono::{DateTime, Duration, Utc};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
-#[derive(Serialize, Deserialize)]
+/// Longest any phase may be set to last.
+pub const MAX_LIMIT_SECS: i64 = 24 * 60 * 60;
+
+/// Out of range values are clamped to just past what `validate` accepts, so
+/// they're reported as a violation rather than overflowing.
+fn limit_from_secs(secs: i64) -> Duration {
+    Duration::seconds(secs.clamp(0, MAX_LIMIT_SECS + 1))
+}
+
+/// Names of the rulesets built into the engine.
+pub const PRESETS: [&str; 3] = ["classic", "speed", "big-town"];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
 pub struct Ruleset {
+    min_players: usize,
+
+    /// Fraction of living players that must vote to skip before the
+    /// morning is cut short, or the day ends without a lynch.
+    skip_threshold: f64,
+
+    roles: RoleDistribution,
+
+    /// Whether a player's role is announced when they die.
+    reveal_on_death: bool,
+
+    /// Whether dead players can follow the mafia's private chat and see
+    /// night actions as they are chosen.
+    dead_spectate: bool,
+
+    whispers: WhisperRules,
+
     #[serde(serialize_with = "se_opt_dur", deserialize_with = "de_opt_dur")]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<OptSecsSchema>"))]
     morning_limit: Option<Duration>,
 
     #[serde(serialize_with = "se_opt_dur", deserialize_with = "de_opt_dur")]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<OptSecsSchema>"))]
     vote_limit: Option<Duration>,
+
+    #[serde(serialize_with = "se_opt_dur", deserialize_with = "de_opt_dur")]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<OptSecsSchema>"))]
+    last_words_limit: Option<Duration>,
+
+    #[serde(serialize_with = "se_opt_dur", deserialize_with = "de_opt_dur")]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<OptSecsSchema>"))]
+    evening_limit: Option<Duration>,
+
+    #[serde(serialize_with = "se_opt_dur", deserialize_with = "de_opt_dur")]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<OptSecsSchema>"))]
+    night_limit: Option<Duration>,
 }
 
 impl Ruleset {
-    pub fn morning_end(&self) -> Option<DateTime<Utc>> {
-        match self.morning_limit {
-            Some(l) => Some(Utc::now() + l),
-            None => None,
+    pub fn min_players(&self) -> usize {
+        self.min_players
+    }
+
+    pub fn skip_votes_required(&self, living: usize) -> usize {
+        let required = (living as f64 * self.skip_threshold).ceil() as usize;
+        required.max(1)
+    }
+
+    pub fn roles(&self) -> &RoleDistribution {
+        &self.roles
+    }
+
+    pub fn reveal_on_death(&self) -> bool {
+        self.reveal_on_death
+    }
+
+    pub fn dead_spectate(&self) -> bool {
+        self.dead_spectate
+    }
+
+    pub fn whispers(&self) -> &WhisperRules {
+        &self.whispers
+    }
+
+    pub fn morning_end(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.morning_limit.map(|l| now + l)
+    }
+
+    pub fn vote_end(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.vote_limit.map(|l| now + l)
+    }
+
+    pub fn last_words_end(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.last_words_limit.map(|l| now + l)
+    }
+
+    pub fn evening_end(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.evening_limit.map(|l| now + l)
+    }
+
+    pub fn night_end(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.night_limit.map(|l| now + l)
+    }
+
+    pub fn init_phase(&self) -> Phase {
+        Lobby {}.into()
+    }
+
+    /// One of the rulesets built into the engine, by name. See `PRESETS`.
+    pub fn preset(name: &str) -> Option<Ruleset> {
+        match name {
+            "classic" => Some(Ruleset::default()),
+            "speed" => Some(Ruleset {
+                morning_limit: Some(Duration::seconds(60)),
+                vote_limit: Some(Duration::seconds(45)),
+                last_words_limit: Some(Duration::seconds(15)),
+                evening_limit: Some(Duration::seconds(20)),
+                night_limit: Some(Duration::seconds(30)),
+                ..Ruleset::default()
+            }),
+            "big-town" => Some(Ruleset {
+                min_players: 10,
+                roles: RoleDistribution::Ratio {
+                    ratios: vec![
+                        RoleRatio {
+                            role: Role::Mafioso,
+                            per: 4,
+                        },
+                        RoleRatio {
+                            role: Role::Doctor,
+                            per: 5,
+                        },
+                        RoleRatio {
+                            role: Role::Detective,
+                            per: 6,
+                        },
+                        RoleRatio {
+                            role: Role::Bartender,
+                            per: 7,
+                        },
+                    ],
+                },
+                morning_limit: Some(Duration::minutes(5)),
+                vote_limit: Some(Duration::minutes(2)),
+                ..Ruleset::default()
+            }),
+            _ => None,
         }
     }
 
-    pub fn vote_end(&self) -> Option<DateTime<Utc>> {
-        match self.vote_limit {
-            Some(l) => Some(Utc::now() + l),
-            None => None,
+    /// The rules with everything set in `update` replaced.
+    pub fn apply(&self, update: RulesUpdate) -> Ruleset {
+        let dur = |secs: Option<i64>| secs.map(limit_from_secs);
+        let rules = self.clone();
+        Ruleset {
+            min_players: update.min_players.unwrap_or(rules.min_players),
+            skip_threshold: update.skip_threshold.unwrap_or(rules.skip_threshold),
+            roles: update.roles.unwrap_or(rules.roles),
+            reveal_on_death: update.reveal_on_death.unwrap_or(rules.reveal_on_death),
+            dead_spectate: update.dead_spectate.unwrap_or(rules.dead_spectate),
+            whispers: update.whispers.unwrap_or(rules.whispers),
+            morning_limit: update.morning_limit.map_or(rules.morning_limit, dur),
+            vote_limit: update.vote_limit.map_or(rules.vote_limit, dur),
+            last_words_limit: update.last_words_limit.map_or(rules.last_words_limit, dur),
+            evening_limit: update.evening_limit.map_or(rules.evening_limit, dur),
+            night_limit: update.night_limit.map_or(rules.night_limit, dur),
         }
     }
 
-    pub fn init_phase(&self) -> Phase {
-        Morning {}.into()
+    /// Checks that the rules make for a playable game once `min_players`
+    /// have joined, listing every problem rather than just the first.
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+        if self.min_players < 3 {
+            problems.push(RuleViolation::TooFewPlayers {
+                min_players: self.min_players,
+            });
+        }
+        if !(self.skip_threshold > 0.0 && self.skip_threshold <= 1.0) {
+            problems.push(RuleViolation::SkipThreshold {
+                threshold: self.skip_threshold,
+            });
+        }
+        let limits = [
+            (PhaseName::Morning, self.morning_limit),
+            (PhaseName::Vote, self.vote_limit),
+            (PhaseName::LastWords, self.last_words_limit),
+            (PhaseName::Evening, self.evening_limit),
+            (PhaseName::Night, self.night_limit),
+        ];
+        for (phase, limit) in limits.iter() {
+            // only the morning and the vote can be ended early by the players
+            let required = *phase != PhaseName::Morning && *phase != PhaseName::Vote;
+            if required && limit.is_none() {
+                problems.push(RuleViolation::NoLimit { phase: *phase });
+            }
+            if matches!(limit, Some(l) if *l <= Duration::zero()) {
+                problems.push(RuleViolation::ZeroLengthPhase { phase: *phase });
+            }
+            if matches!(limit, Some(l) if *l > Duration::seconds(MAX_LIMIT_SECS)) {
+                problems.push(RuleViolation::PhaseTooLong {
+                    phase: *phase,
+                    max_secs: MAX_LIMIT_SECS,
+                });
+            }
+        }
+        problems.extend(self.roles.check(self.min_players));
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidRules(problems))
+        }
+    }
+}
+
+/// A partial `Ruleset`, anything left out keeps its current value. Time
+/// limits are in seconds, with `null` meaning no limit at all (only allowed
+/// for the morning and the vote).
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct RulesUpdate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_players: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skip_threshold: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roles: Option<RoleDistribution>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reveal_on_death: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dead_spectate: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub whispers: Option<WhisperRules>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "de_patch_secs"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Option<OptSecsSchema>>"))]
+    pub morning_limit: Option<Option<i64>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "de_patch_secs"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Option<OptSecsSchema>>"))]
+    pub vote_limit: Option<Option<i64>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "de_patch_secs"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Option<OptSecsSchema>>"))]
+    pub last_words_limit: Option<Option<i64>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "de_patch_secs"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Option<OptSecsSchema>>"))]
+    pub evening_limit: Option<Option<i64>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "de_patch_secs"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Option<OptSecsSchema>>"))]
+    pub night_limit: Option<Option<i64>>,
+}
+
+/// Something that makes a ruleset unplayable.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(tag = "problem")]
+pub enum RuleViolation {
+    TooFewPlayers {
+        min_players: usize,
+    },
+    /// The skip threshold has to be a fraction of the living players.
+    SkipThreshold {
+        threshold: f64,
+    },
+    ZeroLengthPhase {
+        phase: PhaseName,
+    },
+    PhaseTooLong {
+        phase: PhaseName,
+        max_secs: i64,
+    },
+    /// Nothing but the timer ends this phase, so without one the game would
+    /// never move on.
+    NoLimit {
+        phase: PhaseName,
+    },
+    TooManyRoles {
+        roles: usize,
+        players: usize,
+    },
+    /// The town would win before the game got going.
+    NoMafia,
+    /// The mafia would win before the game got going.
+    TooManyMafia {
+        mafia: usize,
+        players: usize,
+    },
+    EmptyRolePool,
+    /// Role counts or weights too large to add up.
+    Overflow,
+}
+
+impl fmt::Display for RuleViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use RuleViolation::*;
+        match self {
+            TooFewPlayers { min_players } => {
+                write!(f, "at least 3 players are needed, not {}", min_players)
+            }
+            SkipThreshold { threshold } => write!(
+                f,
+                "skip threshold must be above 0 and at most 1, not {}",
+                threshold
+            ),
+            ZeroLengthPhase { phase } => write!(f, "{:?} can't have a zero length", phase),
+            PhaseTooLong { phase, max_secs } => {
+                write!(f, "{:?} can't last longer than {} seconds", phase, max_secs)
+            }
+            NoLimit { phase } => write!(f, "{:?} needs a time limit", phase),
+            TooManyRoles { roles, players } => write!(
+                f,
+                "{} roles are handed out but the game can start with {} players",
+                roles, players
+            ),
+            NoMafia => write!(f, "nobody would be in the mafia"),
+            TooManyMafia { mafia, players } => write!(
+                f,
+                "{} mafia out of {} players would win straight away",
+                mafia, players
+            ),
+            EmptyRolePool => write!(f, "the role pool is empty"),
+            Overflow => write!(f, "the role counts or weights are too large"),
+        }
     }
 }
 
 impl Default for Ruleset {
     fn default() -> Self {
         Ruleset {
+            min_players: 4,
+            skip_threshold: 0.5,
+            roles: RoleDistribution::default(),
+            reveal_on_death: true,
+            dead_spectate: false,
+            whispers: WhisperRules::default(),
             morning_limit: Some(Duration::minutes(3)),
             vote_limit: Some(Duration::seconds(90)),
+            last_words_limit: Some(Duration::seconds(30)),
+            evening_limit: Some(Duration::seconds(45)),
+            night_limit: Some(Duration::seconds(60)),
+        }
+    }
+}
+
+/// Whether whispers are allowed in each phase of the day, and if so whether
+/// everyone else is told that they happened.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct WhisperRules {
+    pub morning: WhisperMode,
+    pub vote: WhisperMode,
+    pub evening: WhisperMode,
+    pub night: WhisperMode,
+}
+
+impl WhisperRules {
+    /// How whispers work during `phase`, disabled outside the day and night.
+    pub fn mode(&self, phase: PhaseName) -> WhisperMode {
+        match phase {
+            PhaseName::Morning => self.morning,
+            PhaseName::Vote => self.vote,
+            PhaseName::Evening => self.evening,
+            PhaseName::Night => self.night,
+            _ => WhisperMode::Disabled,
+        }
+    }
+}
+
+impl Default for WhisperRules {
+    fn default() -> Self {
+        WhisperRules {
+            morning: WhisperMode::Announced,
+            vote: WhisperMode::Announced,
+            evening: WhisperMode::Announced,
+            night: WhisperMode::Disabled,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, PartialEq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub enum WhisperMode {
+    Disabled,
+    /// Only the two players involved know about the whisper.
+    Secret,
+    /// Everyone else sees "X whispered to Y", but not what was said.
+    Announced,
+}
+
+/// How roles are handed out when the game starts. Any player not given a
+/// role by the distribution is a `Townie`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(tag = "kind")]
+pub enum RoleDistribution {
+    /// Exactly `count` of each role, regardless of the number of players.
+    Fixed { counts: Vec<RoleCount> },
+    /// One of each role for every `per` players, rounded down.
+    Ratio { ratios: Vec<RoleRatio> },
+    /// Every player draws their role independently from a weighted pool,
+    /// redrawing as needed so there is at least one mafioso and the mafia
+    /// make up less than half the players.
+    Weighted { pool: Vec<RoleWeight> },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct RoleCount {
+    pub role: Role,
+    pub count: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct RoleRatio {
+    pub role: Role,
+    pub per: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct RoleWeight {
+    pub role: Role,
+    pub weight: u32,
+}
+
+impl RoleDistribution {
+    /// Produces one role per player, in no particular order. Callers are
+    /// expected to shuffle the result before handing it out.
+    pub fn deal<R: Rng>(&self, players: usize, rng: &mut R) -> Result<Vec<Role>> {
+        // refuse before allocating anything for counts that can't be dealt
+        let dealt = match self {
+            RoleDistribution::Fixed { counts } => total(counts.iter().map(|c| c.count)),
+            RoleDistribution::Ratio { ratios } => {
+                total(ratios.iter().filter(|r| r.per > 0).map(|r| players / r.per))
+            }
+            RoleDistribution::Weighted { pool } => {
+                if total_weight(pool).is_none() {
+                    return Err(Error::InvalidRules(vec![RuleViolation::Overflow]));
+                }
+                Some(players)
+            }
+        };
+        match dealt {
+            None => return Err(Error::InvalidRules(vec![RuleViolation::Overflow])),
+            Some(roles) if roles > players => return Err(Error::TooManyRoles { roles, players }),
+            _ => (),
         }
+        let mut roles = match self {
+            RoleDistribution::Fixed { counts } => {
+                counts.iter().flat_map(|c| vec![c.role; c.count]).collect()
+            }
+            RoleDistribution::Ratio { ratios } => ratios
+                .iter()
+                .filter(|r| r.per > 0)
+                .flat_map(|r| vec![r.role; players / r.per])
+                .collect(),
+            RoleDistribution::Weighted { pool } => {
+                match WeightedIndex::new(pool.iter().map(|w| w.weight)) {
+                    Ok(dist) => {
+                        let mut roles: Vec<Role> =
+                            (0..players).map(|_| pool[dist.sample(rng)].role).collect();
+                        balance(pool, &mut roles, rng);
+                        roles
+                    }
+                    Err(_) => Vec::new(),
+                }
+            }
+        };
+        roles.resize(players, Role::Townie);
+        Ok(roles)
+    }
+
+    /// What would be wrong with dealing roles to `players` players. A
+    /// weighted pool can't be judged by its counts, only by what's in it.
+    pub fn check(&self, players: usize) -> Vec<RuleViolation> {
+        let is_mafia = |role: Role| role.team() == Team::Mafia;
+        let (mafia, roles) = match self {
+            RoleDistribution::Fixed { counts } => {
+                if let Some(c) = counts.iter().find(|c| c.count > players) {
+                    return vec![RuleViolation::TooManyRoles {
+                        roles: c.count,
+                        players,
+                    }];
+                }
+                (
+                    total(counts.iter().filter(|c| is_mafia(c.role)).map(|c| c.count)),
+                    total(counts.iter().map(|c| c.count)),
+                )
+            }
+            RoleDistribution::Ratio { ratios } => {
+                let dealt = |r: &&RoleRatio| r.per > 0;
+                (
+                    total(
+                        ratios
+                            .iter()
+                            .filter(dealt)
+                            .filter(|r| is_mafia(r.role))
+                            .map(|r| players / r.per),
+                    ),
+                    total(ratios.iter().filter(dealt).map(|r| players / r.per)),
+                )
+            }
+            RoleDistribution::Weighted { pool } => {
+                let mafia: u64 = pool
+                    .iter()
+                    .filter(|w| is_mafia(w.role))
+                    .map(|w| u64::from(w.weight))
+                    .sum();
+                return match total_weight(pool) {
+                    None => vec![RuleViolation::Overflow],
+                    Some(0) => vec![RuleViolation::EmptyRolePool],
+                    Some(_) if mafia == 0 => vec![RuleViolation::NoMafia],
+                    Some(_) => Vec::new(),
+                };
+            }
+        };
+        let (mafia, roles) = match (mafia, roles) {
+            (Some(mafia), Some(roles)) => (mafia, roles),
+            _ => return vec![RuleViolation::Overflow],
+        };
+        let mut problems = Vec::new();
+        if roles > players {
+            problems.push(RuleViolation::TooManyRoles { roles, players });
+        }
+        if mafia == 0 {
+            problems.push(RuleViolation::NoMafia);
+        } else if mafia >= players - players / 2 {
+            // i.e. `mafia * 2 >= players`, without overflowing
+            problems.push(RuleViolation::TooManyMafia { mafia, players });
+        }
+        problems
+    }
+}
+
+/// The sum of `counts`, or `None` if it doesn't fit in a `usize`.
+fn total<I: Iterator<Item = usize>>(mut counts: I) -> Option<usize> {
+    counts.try_fold(0usize, |sum, n| sum.checked_add(n))
+}
+
+/// The sum of the pool's weights, or `None` if it's too large to draw from.
+fn total_weight(pool: &[RoleWeight]) -> Option<u32> {
+    pool.iter()
+        .try_fold(0u32, |sum, w| sum.checked_add(w.weight))
+}
+
+/// Independent draws from a weighted pool can deal no mafia at all, or so
+/// many that the town can't win. Redraws from the matching half of the pool
+/// until there is at least one mafioso and the mafia are under half the table.
+fn balance<R: Rng>(pool: &[RoleWeight], roles: &mut [Role], rng: &mut R) {
+    let is_mafia = |role: Role| role.team() == Team::Mafia;
+    let draw = |rng: &mut R, mafia: bool, fallback: Role| {
+        let side: Vec<&RoleWeight> = pool.iter().filter(|w| is_mafia(w.role) == mafia).collect();
+        match WeightedIndex::new(side.iter().map(|w| w.weight)) {
+            Ok(dist) => side[dist.sample(rng)].role,
+            Err(_) => fallback,
+        }
+    };
+    let max_mafia = (roles.len().saturating_sub(1) / 2).max(1);
+    loop {
+        let (mafia, town): (Vec<usize>, Vec<usize>) =
+            (0..roles.len()).partition(|&i| is_mafia(roles[i]));
+        if mafia.is_empty() && !town.is_empty() {
+            let i = town[rng.gen_range(0, town.len())];
+            roles[i] = draw(rng, true, Role::Mafioso);
+        } else if mafia.len() > max_mafia {
+            let i = mafia[rng.gen_range(0, mafia.len())];
+            roles[i] = draw(rng, false, Role::Townie);
+        } else {
+            return;
+        }
+    }
+}
+
+impl Default for RoleDistribution {
+    fn default() -> Self {
+        RoleDistribution::Ratio {
+            ratios: vec![
+                RoleRatio {
+                    role: Role::Mafioso,
+                    per: 4,
+                },
+                RoleRatio {
+                    role: Role::Doctor,
+                    per: 6,
+                },
+                RoleRatio {
+                    role: Role::Detective,
+                    per: 7,
+                },
+                RoleRatio {
+                    role: Role::Bartender,
+                    per: 9,
+                },
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_pcg::Pcg64;
+
+    fn rules(update: serde_json::Value) -> Ruleset {
+        Ruleset::default().apply(serde_json::from_value(update).unwrap())
+    }
+
+    #[test]
+    fn huge_counts_are_rejected() {
+        let fixed = rules(serde_json::json!({ "roles": { "kind": "Fixed", "counts": [
+            { "role": "Mafioso", "count": 1 },
+            { "role": "Townie", "count": usize::MAX },
+        ] } }));
+        assert!(fixed.validate().is_err());
+        let mut rng = Pcg64::seed_from_u64(0);
+        assert!(fixed.roles().deal(5, &mut rng).is_err());
+
+        let ratio = rules(serde_json::json!({
+            "min_players": usize::MAX,
+            "roles": { "kind": "Ratio", "ratios": [
+                { "role": "Mafioso", "per": 1 },
+                { "role": "Doctor", "per": 1 },
+            ] },
+        }));
+        assert!(ratio.validate().is_err());
+
+        let weighted = rules(serde_json::json!({ "roles": { "kind": "Weighted", "pool": [
+            { "role": "Mafioso", "weight": u32::MAX },
+            { "role": "Townie", "weight": u32::MAX },
+        ] } }));
+        assert!(weighted.validate().is_err());
+        assert!(weighted.roles().deal(5, &mut rng).is_err());
     }
 }
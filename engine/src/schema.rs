@@ -0,0 +1,15 @@
+//! Machine readable JSON schemas for the wire protocol, so that clients not
+//! written in rust can validate what they send and receive.
+
+use crate::action::Action;
+use crate::response::Response;
+use schemars::schema::RootSchema;
+use schemars::schema_for;
+
+pub fn action() -> RootSchema {
+    schema_for!(Action)
+}
+
+pub fn response() -> RootSchema {
+    schema_for!(Response)
+}
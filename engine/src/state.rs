@@ -1,15 +1,22 @@
+use crate::action::{Action, ActionE, ActionMessage};
 use crate::error::Error;
-use crate::phases::{Evening, LastWords, Lobby, Morning, Night, Vote};
-use crate::player::{Player, PlayerName, PlayerNameRef};
+use crate::event::Event;
+use crate::phases::{Evening, GameOver, LastWords, Lobby, Morning, Night, Vote};
+use crate::player::{Player, PlayerName, PlayerNameRef, PlayerState};
 use crate::player_connection::PlayerConnection;
-use crate::ruleset::Ruleset;
+use crate::recap::Recap;
+use crate::response::{Channel, Response};
+use crate::ruleset::{Ruleset, WhisperMode};
+use crate::util::{broadcast, message_if};
+use crate::view::{GameSummary, PhaseName, PlayerView};
 use crate::Result;
 use chrono::{DateTime, Utc};
 use im::{vector, HashSet, Vector};
 use log::debug;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
 pub struct RootState<PC: PlayerConnection> {
     pub day: u32,
     pub players: Vector<Player<PC>>,
@@ -17,9 +24,19 @@ pub struct RootState<PC: PlayerConnection> {
     pub vote_skip: HashSet<PlayerName>,
     pub next_state_time: Option<DateTime<Utc>>,
     pub host: PlayerName,
+    /// Names the host has banned from joining.
+    #[serde(default)]
+    pub banned: HashSet<PlayerName>,
+    /// Set once the host closes the lobby, nothing more can happen after.
+    #[serde(default)]
+    pub closed: bool,
+    pub seed: u64,
+    /// Everything that has happened in the game so far, oldest first.
+    pub events: Vector<Event>,
 }
 
 #[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
 pub struct State<PC: PlayerConnection> {
     #[serde(flatten)]
     root: RootState<PC>,
@@ -28,25 +45,250 @@ pub struct State<PC: PlayerConnection> {
     phase: Phase,
 }
 
+impl<PC: PlayerConnection> RootState<PC> {
+    pub fn get_player(&self, player_name: PlayerNameRef) -> Result<&Player<PC>> {
+        self.players
+            .iter()
+            .find(|p| p.get_name() == player_name)
+            .ok_or_else(|| Error::InvalidPlayerName(player_name.to_string()))
+    }
+
+    pub fn living_players(&self) -> impl Iterator<Item = &Player<PC>> {
+        self.players.iter().filter(|p| p.state.is_alive())
+    }
+
+    pub fn require_host(&self, player_name: PlayerNameRef) -> Result<()> {
+        if player_name != self.host {
+            return Err(Error::NotHost);
+        }
+        Ok(())
+    }
+
+    pub fn set_host(&mut self, host: PlayerName) {
+        debug!("{} is now the host", host);
+        self.host = host.clone();
+        broadcast(self.players.iter(), Response::host_changed(host));
+    }
+
+    /// Takes a player out of the game and disconnects them. Their secret goes
+    /// with them, and a banned player can't join again under the same name.
+    pub fn remove_player(&mut self, player_name: PlayerName, ban: bool) -> Result<()> {
+        if player_name == self.host {
+            return Err(Error::InvalidPlayerName(player_name));
+        }
+        let idx = self
+            .players
+            .iter()
+            .position(|p| p.get_name() == player_name)
+            .ok_or_else(|| Error::InvalidPlayerName(player_name.clone()))?;
+        let removed = self.players.remove(idx);
+        if ban {
+            self.banned.insert(player_name.clone());
+        }
+        let notice = Response::kicked(player_name, ban);
+        if let Some(conn) = &removed.connection {
+            conn.send(notice.clone());
+            conn.close();
+        }
+        broadcast(self.players.iter(), notice);
+        Ok(())
+    }
+
+    /// Shuts the game down and disconnects everyone.
+    pub fn close(&mut self) {
+        self.closed = true;
+        for conn in self.players.iter().filter_map(|p| p.connection.as_ref()) {
+            conn.send(Response::lobby_closed());
+            conn.close();
+        }
+    }
+
+    /// Records a vote to skip from a living player and tells everyone the new
+    /// count. Returns true once the ruleset's threshold has been reached.
+    pub fn skip_vote(&mut self, player_name: PlayerName) -> Result<bool> {
+        if !self.get_player(&player_name)?.state.is_alive() {
+            return Err(Error::PlayerDead(player_name));
+        }
+        self.vote_skip.insert(player_name);
+        let count = self.vote_skip.len();
+        let required = self
+            .rules
+            .skip_votes_required(self.living_players().count());
+        broadcast(self.players.iter(), Response::skip_votes(count, required));
+        Ok(count >= required)
+    }
+
+    /// Privately delivers a message from one living player to another, and
+    /// echoes it back to the sender.
+    pub fn whisper(
+        &self,
+        sender: PlayerName,
+        to: PlayerName,
+        text: String,
+        mode: WhisperMode,
+    ) -> Result<()> {
+        if mode == WhisperMode::Disabled {
+            return Err(Error::WrongPhase);
+        }
+        if !self.get_player(&sender)?.state.is_alive() {
+            return Err(Error::PlayerDead(sender));
+        }
+        if sender == to {
+            return Err(Error::InvalidPlayerName(to));
+        }
+        if !self.get_player(&to)?.state.is_alive() {
+            return Err(Error::TargetDead(to));
+        }
+        let spectate = self.rules.dead_spectate();
+        message_if(
+            &sender,
+            ActionMessage { text },
+            || self.players.iter(),
+            Channel::Whisper,
+            |src, dest| {
+                dest.get_name() == to
+                    || dest.get_name() == src.get_name()
+                    || (spectate && !dest.state.is_alive())
+            },
+        )?;
+        if mode == WhisperMode::Announced {
+            broadcast(
+                self.players
+                    .iter()
+                    .filter(|p| p.get_name() != sender && p.get_name() != to),
+                Response::whisper_notice(sender.clone(), to.clone()),
+            );
+        }
+        Ok(())
+    }
+
+    /// Marks a player dead and announces it, revealing their role if the
+    /// ruleset says so.
+    pub fn kill(&mut self, player_name: PlayerNameRef) {
+        debug!("killing player {}", player_name);
+        let role = match self
+            .players
+            .iter_mut()
+            .find(|p| p.get_name() == player_name)
+        {
+            Some(p) => {
+                p.state = PlayerState::Dead;
+                p.role
+            }
+            None => return,
+        };
+        self.events.push_back(Event::Died {
+            day: self.day,
+            player: player_name.to_string(),
+            role,
+        });
+        let revealed = if self.rules.reveal_on_death() {
+            Some(role)
+        } else {
+            None
+        };
+        broadcast(
+            self.players.iter(),
+            Response::death(player_name.to_string(), revealed),
+        );
+    }
+}
+
 impl<PC: PlayerConnection> State<PC> {
     pub fn new(rules: Ruleset, host_name: PlayerName, host_secret: String) -> Self {
+        Self::with_seed(rules, host_name, host_secret, rand::random())
+    }
+
+    /// Like `new`, but with a fixed seed for dealing roles. Whoever knows the
+    /// seed can work out every player's role, so it must never come from a
+    /// player.
+    pub fn with_seed(
+        rules: Ruleset,
+        host_name: PlayerName,
+        host_secret: String,
+        seed: u64,
+    ) -> Self {
+        Self::create(rules, host_name, host_secret, seed, Utc::now())
+    }
+
+    fn create(
+        rules: Ruleset,
+        host_name: PlayerName,
+        host_secret: String,
+        seed: u64,
+        now: DateTime<Utc>,
+    ) -> Self {
         debug!("creating game: host={} secret={}", host_name, host_secret);
-        let next_state_time = rules.morning_end();
         let phase = rules.init_phase();
-        let host = Player::new(host_name.clone(), host_secret);
+        let host = Player::new(host_name.clone(), host_secret.clone());
+        let created = Event::Created {
+            at: now,
+            rules: rules.clone(),
+            host: host_name.clone(),
+            secret: host_secret,
+            seed,
+        };
         State {
             root: RootState {
                 day: 1,
                 players: vector![host],
                 rules,
                 vote_skip: HashSet::new(),
-                next_state_time,
+                next_state_time: None,
                 host: host_name,
+                banned: HashSet::new(),
+                closed: false,
+                seed,
+                events: vector![created],
             },
             phase,
         }
     }
 
+    /// Rebuilds a game from its history. Only the input events are used, the
+    /// derived ones are recorded again as the game is re-run.
+    pub fn replay<I>(events: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = Event>,
+    {
+        let mut inputs = events.into_iter().filter(Event::is_input);
+        let mut state = match inputs.next() {
+            Some(Event::Created {
+                at,
+                rules,
+                host,
+                secret,
+                seed,
+            }) => Self::create(rules, host, secret, seed, at),
+            _ => return Err(Error::InvalidHistory),
+        };
+        for event in inputs {
+            match event {
+                Event::Joined { at, player, secret } => state.join_at(player, secret, at)?,
+                Event::Action { at, player, action } => state.act_at(player, action, at)?,
+                Event::Tick { at } => {
+                    state.tick(at);
+                }
+                Event::HostMigrated { at, host } => state.migrate_host_to(host, at),
+                _ => return Err(Error::InvalidHistory),
+            }
+        }
+        Ok(state)
+    }
+
+    pub fn events(&self) -> &Vector<Event> {
+        &self.root.events
+    }
+
+    /// The post-game summary, only available once the game is over so it
+    /// can't be used to peek at roles.
+    pub fn recap(&self) -> Result<Recap> {
+        match self.phase {
+            Phase::GameOver(_) => Ok(Recap::from_events(self.root.events.iter())),
+            _ => Err(Error::GameInProgress),
+        }
+    }
+
     pub fn get_playername(&self, secret: &str) -> Option<PlayerName> {
         self.root
             .players
@@ -80,27 +322,212 @@ impl<PC: PlayerConnection> State<PC> {
             .players
             .iter_mut()
             .find(|p| p.get_name() == player_name);
-        if let Some(player) = player_o {
-            player.connection = conn;
+        match player_o {
+            Some(player) => player.connection = conn.clone(),
+            None => return,
         }
+        if let Some(c) = &conn {
+            c.send(Response::hello(player_name.clone()));
+            if let Ok(view) = self.view_for(&player_name) {
+                c.send(Response::snapshot(view));
+            }
+        }
+    }
+
+    /// A snapshot of the game with everything the player shouldn't know
+    /// left out.
+    pub fn view_for(&self, player_name: PlayerNameRef) -> Result<PlayerView> {
+        self.root.get_player(player_name)?;
+        Ok(PlayerView::new(
+            &self.root,
+            &self.phase,
+            player_name.to_string(),
+        ))
+    }
+
+    pub fn summary(&self) -> GameSummary {
+        GameSummary::new(&self.root, &self.phase)
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.phase.name() == PhaseName::GameOver
+    }
+
+    /// Whether anyone is still connected to the game.
+    pub fn has_connections(&self) -> bool {
+        self.root
+            .players
+            .iter()
+            .any(|p| p.connection.iter().any(|c| c.is_alive()))
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.root.closed
+    }
+
+    pub fn host_connected(&self) -> bool {
+        self.root
+            .get_player(&self.root.host)
+            .map(|p| p.connection.iter().any(|c| c.is_alive()))
+            .unwrap_or(false)
+    }
+
+    /// Hands hosting to the next connected player after the current host,
+    /// for when the host has gone missing. Returns the new host, if anyone
+    /// was connected to take over.
+    pub fn migrate_host(&mut self) -> Option<PlayerName> {
+        let players = &self.root.players;
+        let start = players
+            .iter()
+            .position(|p| p.get_name() == self.root.host)
+            .map_or(0, |i| i + 1);
+        let host = players
+            .iter()
+            .skip(start)
+            .chain(players.iter().take(start))
+            .filter(|p| p.get_name() != self.root.host)
+            .find(|p| p.connection.iter().any(|c| c.is_alive()))?
+            .get_name()
+            .to_string();
+        self.migrate_host_to(host.clone(), Utc::now());
+        Some(host)
+    }
+
+    fn migrate_host_to(&mut self, host: PlayerName, now: DateTime<Utc>) {
+        self.root.events.push_back(Event::HostMigrated {
+            at: now,
+            host: host.clone(),
+        });
+        self.root.set_host(host);
     }
 
     pub fn create_user(&mut self, player_name: PlayerName, secret: String) -> Result<()> {
+        self.join_at(player_name, secret, Utc::now())
+    }
+
+    fn join_at(
+        &mut self,
+        player_name: PlayerName,
+        secret: String,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        if self.root.closed {
+            return Err(Error::LobbyClosed);
+        }
+        if !self.phase.same_phase(&Lobby {}.into()) {
+            return Err(Error::GameInProgress);
+        }
+        if self.root.banned.contains(&player_name) {
+            return Err(Error::Banned(player_name));
+        }
         if self
             .root
             .players
             .iter()
             .any(|p| p.get_name() == player_name)
         {
-            return Err(Error::InvalidPlayerName(player_name));
+            return Err(Error::PlayerNameTaken(player_name));
         }
-        let player = Player::new(player_name, secret);
+        let player = Player::new(player_name.clone(), secret.clone());
         self.root.players.push_back(player);
+        self.root.events.push_back(Event::Joined {
+            at: now,
+            player: player_name,
+            secret,
+        });
         Ok(())
     }
+
+    pub fn handle_action(&mut self, player_name: PlayerName, act: Action) -> Result<()> {
+        self.act_at(player_name, act.a, Utc::now())
+    }
+
+    fn act_at(&mut self, player_name: PlayerName, act: ActionE, now: DateTime<Utc>) -> Result<()> {
+        debug!("handling action from player {}", player_name);
+        if self.root.closed {
+            return Err(Error::LobbyClosed);
+        }
+        if !self
+            .root
+            .players
+            .iter()
+            .any(|p| p.get_name() == player_name)
+        {
+            return Err(Error::InvalidPlayerName(player_name));
+        }
+        let mut root = self.root.clone();
+        root.events.push_back(Event::Action {
+            at: now,
+            player: player_name.clone(),
+            action: act.clone(),
+        });
+        let (phase, root) = match (self.phase.clone(), act) {
+            (phase, ActionE::TransferHost { to }) => {
+                root.require_host(&player_name)?;
+                root.get_player(&to)?;
+                root.set_host(to);
+                Ok((phase, root))
+            }
+            (Phase::GameOver(p), act) => p.handle_action(root, player_name, act, now),
+            (phase, ActionE::Whisper { to, text }) => {
+                let mode = root.rules.whispers().mode(phase.name());
+                root.whisper(player_name, to, text, mode)?;
+                Ok((phase, root))
+            }
+            (Phase::Lobby(p), act) => p.handle_action(root, player_name, act, now),
+            (Phase::Morning(p), act) => p.handle_action(root, player_name, act, now),
+            (Phase::Vote(p), act) => p.handle_action(root, player_name, act, now),
+            (Phase::LastWords(p), act) => p.handle_action(root, player_name, act, now),
+            (Phase::Evening(p), act) => p.handle_action(root, player_name, act, now),
+            (Phase::Night(p), act) => p.handle_action(root, player_name, act, now),
+        }?;
+        self.set_phase(phase, root, now);
+        Ok(())
+    }
+
+    fn set_phase(&mut self, phase: Phase, mut root: RootState<PC>, now: DateTime<Utc>) {
+        if !phase.same_phase(&self.phase) {
+            root.events.push_back(Event::PhaseChanged {
+                at: now,
+                day: root.day,
+                phase: phase.name(),
+            });
+            broadcast(
+                root.players.iter(),
+                Response::phase_changed(root.day, phase.name(), root.next_state_time),
+            );
+        }
+        self.phase = phase;
+        self.root = root;
+    }
+
+    pub fn tick(&mut self, now: DateTime<Utc>) -> bool {
+        match self.root.next_state_time {
+            Some(t) if t <= now => (),
+            _ => return false,
+        }
+        debug!("advancing phase on day {}", self.root.day);
+        let mut root = self.root.clone();
+        root.events.push_back(Event::Tick { at: now });
+        let (phase, root) = match self.phase.clone() {
+            Phase::Lobby(p) => (p.into(), root),
+            Phase::Morning(p) => p.next_phase(root, now),
+            Phase::Vote(p) => p.next_phase(root, now),
+            Phase::LastWords(p) => p.next_phase(root, now),
+            Phase::Evening(p) => p.next_phase(root, now),
+            Phase::Night(p) => p.next_phase(root, now),
+            Phase::GameOver(p) => (p.into(), root),
+        };
+        self.set_phase(phase, root, now);
+        true
+    }
+
+    pub fn next_state_time(&self) -> Option<DateTime<Utc>> {
+        self.root.next_state_time
+    }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(tag = "phase")]
 pub enum Phase {
     Lobby(Lobby),
@@ -109,9 +536,22 @@ pub enum Phase {
     LastWords(LastWords),
     Evening(Evening),
     Night(Night),
+    GameOver(GameOver),
 }
 
 impl Phase {
+    pub fn name(&self) -> PhaseName {
+        match self {
+            Phase::Lobby(_) => PhaseName::Lobby,
+            Phase::Morning(_) => PhaseName::Morning,
+            Phase::Vote(_) => PhaseName::Vote,
+            Phase::LastWords(_) => PhaseName::LastWords,
+            Phase::Evening(_) => PhaseName::Evening,
+            Phase::Night(_) => PhaseName::Night,
+            Phase::GameOver(_) => PhaseName::GameOver,
+        }
+    }
+
     pub fn same_phase(&self, other: &Phase) -> bool {
         use Phase::*;
         match (self, other) {
@@ -120,13 +560,15 @@ impl Phase {
             | (Vote(_), Vote(_))
             | (LastWords(_), LastWords(_))
             | (Evening(_), Evening(_))
-            | (Night(_), Night(_)) => true,
+            | (Night(_), Night(_))
+            | (GameOver(_), GameOver(_)) => true,
             (Lobby(_), _)
             | (Morning(_), _)
             | (Vote(_), _)
             | (LastWords(_), _)
             | (Evening(_), _)
-            | (Night(_), _) => false,
+            | (Night(_), _)
+            | (GameOver(_), _) => false,
         }
     }
 }
@@ -166,3 +608,9 @@ impl From<Night> for Phase {
         Phase::Night(s)
     }
 }
+
+impl From<GameOver> for Phase {
+    fn from(s: GameOver) -> Self {
+        Phase::GameOver(s)
+    }
+}
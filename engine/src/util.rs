@@ -1,10 +1,15 @@
 use crate::action::ActionMessage;
 use crate::error::{Error, Result};
-use crate::player::{Player, PlayerNameRef};
+use crate::player::{Player, PlayerNameRef, Role};
 use crate::player_connection::PlayerConnection;
-use crate::response::Response;
+use crate::response::{Channel, Response};
 use chrono::Duration;
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::de::{self, Visitor};
 use serde::{Deserialize, Deserializer, Serializer};
+use std::convert::TryFrom;
+use std::fmt;
 use std::result::Result as StdResult;
 
 pub fn se_opt_dur<S: Serializer>(dur: &Option<Duration>, s: S) -> StdResult<S::Ok, S::Error> {
@@ -15,13 +20,124 @@ pub fn se_opt_dur<S: Serializer>(dur: &Option<Duration>, s: S) -> StdResult<S::O
 }
 
 pub fn de_opt_dur<'de, D: Deserializer<'de>>(d: D) -> StdResult<Option<Duration>, D::Error> {
-    Ok(Option::deserialize(d)?.map(Duration::seconds))
+    match OptSecs::deserialize(d)?.0 {
+        // chrono panics on anything it can't hold in milliseconds
+        Some(secs) if secs.checked_mul(1000).is_none() => Err(de::Error::custom(format!(
+            "a duration of {} seconds is out of range",
+            secs
+        ))),
+        secs => Ok(secs.map(Duration::seconds)),
+    }
+}
+
+/// Like `de_opt_dur` but in seconds, for the time limits of a partial
+/// ruleset where leaving the field out and asking for no limit differ.
+pub fn de_patch_secs<'de, D: Deserializer<'de>>(d: D) -> StdResult<Option<Option<i64>>, D::Error> {
+    Ok(Some(OptSecs::deserialize(d)?.0))
+}
+
+/// An optional length of time, written either as a number of seconds or as a
+/// string like `"90s"`, `"3m"` or `"1h30m"`. `null`, `"none"` and `"off"`
+/// mean no limit, the latter two for formats without a null like TOML.
+struct OptSecs(Option<i64>);
+
+/// A number of seconds, or a duration like `"90s"`, `"3m"` or `"1h30m"`.
+/// Only used to describe time limits in the JSON schema.
+#[cfg(feature = "schema")]
+#[derive(JsonSchema)]
+#[serde(untagged)]
+#[allow(dead_code)]
+pub(crate) enum OptSecsSchema {
+    Seconds(i64),
+    Text(String),
+}
+
+impl<'de> Deserialize<'de> for OptSecs {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> StdResult<Self, D::Error> {
+        d.deserialize_any(OptSecsVisitor)
+    }
+}
+
+struct OptSecsVisitor;
+
+impl<'de> Visitor<'de> for OptSecsVisitor {
+    type Value = OptSecs;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "a number of seconds or a duration like \"90s\", \"3m\" or \"1h30m\""
+        )
+    }
+
+    fn visit_none<E: de::Error>(self) -> StdResult<OptSecs, E> {
+        Ok(OptSecs(None))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> StdResult<OptSecs, E> {
+        Ok(OptSecs(None))
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, d: D) -> StdResult<OptSecs, D::Error> {
+        OptSecs::deserialize(d)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> StdResult<OptSecs, E> {
+        Ok(OptSecs(Some(v)))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> StdResult<OptSecs, E> {
+        i64::try_from(v)
+            .map(|v| OptSecs(Some(v)))
+            .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(v), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> StdResult<OptSecs, E> {
+        match v {
+            "none" | "off" => Ok(OptSecs(None)),
+            _ => parse_secs(v)
+                .map(|s| OptSecs(Some(s)))
+                .ok_or_else(|| E::invalid_value(de::Unexpected::Str(v), &self)),
+        }
+    }
+}
+
+fn parse_secs(s: &str) -> Option<i64> {
+    if let Ok(secs) = s.parse() {
+        return Some(secs);
+    }
+    if s.is_empty() {
+        return None;
+    }
+    let mut total: i64 = 0;
+    let mut num = String::new();
+    for c in s.chars() {
+        let unit = match c {
+            '0'..='9' => {
+                num.push(c);
+                continue;
+            }
+            'h' => 60 * 60,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        let part = num.parse::<i64>().ok()?.checked_mul(unit)?;
+        total = total.checked_add(part)?;
+        num.clear();
+    }
+    if num.is_empty() {
+        Some(total)
+    } else {
+        None
+    }
 }
 
 pub fn message_if<'a, PC, I, P, C>(
     sender: PlayerNameRef,
     message: ActionMessage,
     players: P,
+    channel: Channel,
     cond: C,
 ) -> Result<()>
 where
@@ -36,13 +152,18 @@ where
         .next()
         .unwrap_or_else(|| Err(Error::InvalidPlayerName(sender.to_string())))?;
 
-    let targets = players().filter(|dest| cond(src, dest));
+    // nothing a dead player says may ever reach the living, whatever the
+    // caller's condition allows
+    let targets = players()
+        .filter(|dest| src.state.is_alive() || !dest.state.is_alive())
+        .filter(|dest| cond(src, dest));
 
     for target in targets {
         if let Some(conn) = &target.connection {
             conn.send(Response::message(
                 Some(src.get_name().to_string()),
                 message.text.clone(),
+                channel,
             ))
         }
     }
@@ -59,8 +180,104 @@ where
     I: Iterator<Item = &'a Player<PC>>,
     P: Fn() -> I,
 {
-    fn cond<PC: PlayerConnection>(src: &Player<PC>, dest: &Player<PC>) -> bool {
-        src.state.is_alive() || !dest.state.is_alive()
+    let alive = players().any(|p| p.get_name() == sender && p.state.is_alive());
+    if alive {
+        message_if(sender, message, players, Channel::Town, |_, _| true)
+    } else {
+        message_dead(sender, message, players)
+    }
+}
+
+/// The graveyard: dead players talking among themselves.
+pub fn message_dead<'a, PC, I, P>(
+    sender: PlayerNameRef,
+    message: ActionMessage,
+    players: P,
+) -> Result<()>
+where
+    PC: PlayerConnection + 'a,
+    I: Iterator<Item = &'a Player<PC>>,
+    P: Fn() -> I,
+{
+    message_if(sender, message, players, Channel::Dead, |_, dest| {
+        !dest.state.is_alive()
+    })
+}
+
+/// Sends a response to every dead player, used to let them spectate when
+/// the ruleset allows it.
+pub fn send_spectators<'a, PC, I>(players: I, resp: Response)
+where
+    PC: PlayerConnection + 'a,
+    I: Iterator<Item = &'a Player<PC>>,
+{
+    broadcast(players.filter(|p| !p.state.is_alive()), resp)
+}
+
+/// Living mafia talk among themselves, dead players only listen in when the
+/// ruleset lets them spectate.
+pub fn message_mafia<'a, PC, I, P>(
+    sender: PlayerNameRef,
+    message: ActionMessage,
+    players: P,
+    spectate: bool,
+) -> Result<()>
+where
+    PC: PlayerConnection + 'a,
+    I: Iterator<Item = &'a Player<PC>>,
+    P: Fn() -> I,
+{
+    let cond = |_: &Player<PC>, dest: &Player<PC>| {
+        if dest.state.is_alive() {
+            dest.role == Role::Mafioso
+        } else {
+            spectate
+        }
+    };
+    message_if(sender, message, players, Channel::Mafia, cond)
+}
+
+pub fn broadcast<'a, PC, I>(players: I, resp: Response)
+where
+    PC: PlayerConnection + 'a,
+    I: Iterator<Item = &'a Player<PC>>,
+{
+    for player in players {
+        if let Some(conn) = &player.connection {
+            conn.send(resp.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_secs;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_secs("90"), Some(90));
+        assert_eq!(parse_secs("-5"), Some(-5));
+        assert_eq!(parse_secs("90s"), Some(90));
+        assert_eq!(parse_secs("3m"), Some(180));
+        assert_eq!(parse_secs("1h30m"), Some(5400));
+        assert_eq!(parse_secs("1h1m1s"), Some(3661));
+        assert_eq!(parse_secs("0s"), Some(0));
+    }
+
+    #[test]
+    fn rejects_bad_durations() {
+        assert_eq!(parse_secs(""), None);
+        assert_eq!(parse_secs("3m5"), None);
+        assert_eq!(parse_secs("3x"), None);
+        assert_eq!(parse_secs("m"), None);
+        assert_eq!(parse_secs("1.5m"), None);
+        assert_eq!(parse_secs(" 90s"), None);
+    }
+
+    #[test]
+    fn rejects_overflowing_durations() {
+        assert_eq!(parse_secs("9999999999999999h"), None);
+        assert_eq!(parse_secs("99999999999999999999s"), None);
+        assert_eq!(parse_secs("9223372036854775807s1s"), None);
     }
-    message_if(sender, message, players, cond)
 }
@@ -0,0 +1,138 @@
+use crate::player::{PlayerName, Role, Team};
+use crate::player_connection::PlayerConnection;
+use crate::ruleset::Ruleset;
+use crate::state::{Phase, RootState};
+use chrono::{DateTime, Utc};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Everything one player is allowed to know about the game, used to bring a
+/// client up to date when it (re)connects.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct PlayerView {
+    pub name: PlayerName,
+    pub host: PlayerName,
+    pub day: u32,
+    pub phase: PhaseName,
+    pub next_state_time: Option<DateTime<Utc>>,
+    /// `None` until roles are handed out at the start of the game.
+    pub role: Option<Role>,
+    /// Other players known to be on the same team, only the mafia know
+    /// each other.
+    pub allies: Vec<PlayerName>,
+    pub players: Vec<PlayerSummary>,
+    /// target -> voters, only during the vote.
+    pub votes: Option<BTreeMap<PlayerName, Vec<PlayerName>>>,
+    /// Who is giving their last words.
+    pub condemned: Option<PlayerName>,
+    pub winner: Option<Team>,
+    pub rules: Ruleset,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct PlayerSummary {
+    pub name: PlayerName,
+    pub alive: bool,
+    pub connected: bool,
+    /// Only known for dead players when the ruleset reveals roles on death,
+    /// or for everyone once the game is over.
+    pub role: Option<Role>,
+}
+
+/// What's public about a game, enough for someone browsing for one to join.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GameSummary {
+    pub host: PlayerName,
+    pub players: usize,
+    pub phase: PhaseName,
+    pub rules: Ruleset,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, PartialEq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub enum PhaseName {
+    Lobby,
+    Morning,
+    Vote,
+    LastWords,
+    Evening,
+    Night,
+    GameOver,
+}
+
+impl PlayerView {
+    pub fn new<PC>(root: &RootState<PC>, phase: &Phase, name: PlayerName) -> Self
+    where
+        PC: PlayerConnection,
+    {
+        let started = phase.name() != PhaseName::Lobby;
+        let game_over = phase.name() == PhaseName::GameOver;
+        let me = root.players.iter().find(|p| p.get_name() == name);
+        let role = me.filter(|_| started).map(|p| p.role);
+        let allies = match role {
+            Some(Role::Mafioso) => root
+                .players
+                .iter()
+                .filter(|p| p.role == Role::Mafioso && p.get_name() != name)
+                .map(|p| p.get_name().to_string())
+                .collect(),
+            _ => Vec::new(),
+        };
+        let players = root
+            .players
+            .iter()
+            .map(|p| {
+                let alive = p.state.is_alive();
+                let revealed = game_over || (!alive && root.rules.reveal_on_death());
+                PlayerSummary {
+                    name: p.get_name().to_string(),
+                    alive,
+                    connected: p.connection.iter().any(|c| c.is_alive()),
+                    role: if revealed { Some(p.role) } else { None },
+                }
+            })
+            .collect();
+
+        PlayerView {
+            host: root.host.clone(),
+            day: root.day,
+            phase: phase.name(),
+            next_state_time: root.next_state_time,
+            role,
+            allies,
+            players,
+            votes: match phase {
+                Phase::Vote(v) => Some(v.tally()),
+                _ => None,
+            },
+            condemned: match phase {
+                Phase::LastWords(l) => Some(l.condemned.clone()),
+                _ => None,
+            },
+            winner: match phase {
+                Phase::GameOver(g) => Some(g.winner),
+                _ => None,
+            },
+            rules: root.rules.clone(),
+            name,
+        }
+    }
+}
+
+impl GameSummary {
+    pub fn new<PC>(root: &RootState<PC>, phase: &Phase) -> Self
+    where
+        PC: PlayerConnection,
+    {
+        GameSummary {
+            host: root.host.clone(),
+            players: root.players.len(),
+            phase: phase.name(),
+            rules: root.rules.clone(),
+        }
+    }
+}
@@ -0,0 +1,570 @@
+use mafia::{
+    Action, Error, Event, NightOutcome, PhaseName, PlayerConnection, Response, Role, Ruleset,
+    State, Team,
+};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+/// Remembers everything sent to it, as json.
+#[derive(Clone, Default)]
+struct Conn(Rc<RefCell<Vec<serde_json::Value>>>);
+
+impl PlayerConnection for Conn {
+    fn send(&self, resp: Response) {
+        self.0
+            .borrow_mut()
+            .push(serde_json::to_value(resp).unwrap());
+    }
+
+    fn is_alive(&self) -> bool {
+        true
+    }
+
+    fn close(&self) {}
+}
+
+impl Conn {
+    fn received(&self, kind: &str) -> bool {
+        self.0.borrow().iter().any(|r| r["type"] == kind)
+    }
+}
+
+fn act(json: serde_json::Value) -> Action {
+    serde_json::from_value(json).unwrap()
+}
+
+/// A game with `names` joined and connected, the first being the host.
+fn game(names: &[&str]) -> (State<Conn>, BTreeMap<String, Conn>) {
+    let mut state = State::new(Ruleset::default(), names[0].into(), "secret0".into());
+    let mut conns = BTreeMap::new();
+    for (i, name) in names.iter().enumerate() {
+        if i > 0 {
+            state
+                .create_user(name.to_string(), format!("secret{}", i))
+                .unwrap();
+        }
+        let conn = Conn::default();
+        state.register_connection(name.to_string(), Some(conn.clone()));
+        conns.insert(name.to_string(), conn);
+    }
+    (state, conns)
+}
+
+/// Deals a fixed set of roles, filling the rest of the table with townies.
+fn start(state: &mut State<Conn>, host: &str, roles: &[(Role, usize)]) -> BTreeMap<String, Role> {
+    let counts: Vec<_> = roles
+        .iter()
+        .map(|(role, count)| serde_json::json!({ "role": role, "count": count }))
+        .collect();
+    let update = serde_json::json!({
+        "type": "UpdateRules",
+        "rules": {
+            "min_players": state.summary().players,
+            "roles": { "kind": "Fixed", "counts": counts },
+        },
+    });
+    state.handle_action(host.into(), act(update)).unwrap();
+    state
+        .handle_action(host.into(), act(serde_json::json!({ "type": "Start" })))
+        .unwrap();
+    state
+        .events()
+        .iter()
+        .find_map(|e| match e {
+            Event::RolesAssigned { roles } => Some(roles.clone()),
+            _ => None,
+        })
+        .unwrap()
+}
+
+/// Runs out the clock on each phase until the game reaches `phase`.
+fn advance_to(state: &mut State<Conn>, phase: PhaseName) {
+    while state.summary().phase != phase {
+        let deadline = state.next_state_time();
+        let stuck = format!("stuck in {:?}", state.summary().phase);
+        assert!(state.tick(deadline.expect(&stuck)), "{}", stuck);
+    }
+}
+
+fn player_with(roles: &BTreeMap<String, Role>, role: Role) -> Vec<String> {
+    roles
+        .iter()
+        .filter(|(_, r)| **r == role)
+        .map(|(name, _)| name.clone())
+        .collect()
+}
+
+fn last_night(state: &State<Conn>) -> NightOutcome {
+    state
+        .events()
+        .iter()
+        .rev()
+        .find_map(|e| match e {
+            Event::NightResolved { outcome, .. } => Some(outcome.clone()),
+            _ => None,
+        })
+        .unwrap()
+}
+
+#[test]
+fn replay_matches_live_game() {
+    let (mut live, _) = game(&["ann", "bob", "cat", "dan", "eve"]);
+    let update = serde_json::json!({
+        "type": "UpdateRules",
+        "rules": { "night_limit": "2m", "reveal_on_death": false },
+    });
+    live.handle_action("ann".into(), act(update)).unwrap();
+    let roles = start(&mut live, "ann", &[(Role::Mafioso, 1)]);
+    let mafioso = player_with(&roles, Role::Mafioso).remove(0);
+    let town = player_with(&roles, Role::Townie);
+
+    advance_to(&mut live, PhaseName::Vote);
+    for voter in roles.keys() {
+        if live.summary().phase != PhaseName::Vote {
+            break;
+        }
+        let vote = serde_json::json!({ "type": "Vote", "target": town[0] });
+        live.handle_action(voter.clone(), act(vote)).unwrap();
+    }
+    while live.summary().phase != PhaseName::GameOver {
+        if live.summary().phase == PhaseName::Night {
+            for target in &town {
+                let kill = serde_json::json!({ "type": "Kill", "target": target });
+                if live.handle_action(mafioso.clone(), act(kill)).is_ok() {
+                    break;
+                }
+            }
+        }
+        live.tick(live.next_state_time().unwrap());
+    }
+
+    let expected = serde_json::to_value(&live).unwrap();
+    let replayed: State<Conn> = State::replay(live.events().iter().cloned()).unwrap();
+    assert_eq!(serde_json::to_value(&replayed).unwrap(), expected);
+
+    let saved = serde_json::to_string(live.events()).unwrap();
+    let events: Vec<Event> = serde_json::from_str(&saved).unwrap();
+    let restored: State<Conn> = State::replay(events).unwrap();
+    assert_eq!(serde_json::to_value(&restored).unwrap(), expected);
+
+    let saved = serde_json::to_string(&live).unwrap();
+    let restored: State<Conn> = serde_json::from_str(&saved).unwrap();
+    assert_eq!(serde_json::to_value(&restored).unwrap(), expected);
+}
+
+#[test]
+fn roleblocks_come_first_and_cannot_be_blocked() {
+    let names = ["ann", "bob", "cat", "dan", "eve", "fay", "gus"];
+    let (mut state, conns) = game(&names);
+    let roles = start(
+        &mut state,
+        "ann",
+        &[
+            (Role::Mafioso, 1),
+            (Role::Bartender, 2),
+            (Role::Doctor, 1),
+            (Role::Detective, 1),
+        ],
+    );
+    let mafioso = player_with(&roles, Role::Mafioso).remove(0);
+    let bartenders = player_with(&roles, Role::Bartender);
+    let doctor = player_with(&roles, Role::Doctor).remove(0);
+    let detective = player_with(&roles, Role::Detective).remove(0);
+    let townie = player_with(&roles, Role::Townie).remove(0);
+
+    advance_to(&mut state, PhaseName::Night);
+    let night = [
+        (&bartenders[0], "Roleblock", &bartenders[1]),
+        (&bartenders[1], "Roleblock", &doctor),
+        (&doctor, "Protect", &townie),
+        (&mafioso, "Kill", &townie),
+        (&detective, "Investigate", &mafioso),
+    ];
+    for (actor, kind, target) in night.iter() {
+        let action = serde_json::json!({ "type": kind, "target": target });
+        state.handle_action(actor.to_string(), act(action)).unwrap();
+    }
+    advance_to(&mut state, PhaseName::Morning);
+
+    let outcome = last_night(&state);
+    assert_eq!(outcome.roleblocked, vec![doctor.clone()]);
+    assert!(outcome.protected.is_empty());
+    assert_eq!(outcome.attacked.as_ref(), Some(&townie));
+    assert!(!outcome.saved);
+    assert_eq!(
+        outcome.investigations.get(&detective),
+        Some(&(mafioso.clone(), Role::Mafioso))
+    );
+    assert!(conns[&doctor].received("Roleblocked"));
+    assert!(!conns[&bartenders[1]].received("Roleblocked"));
+}
+
+#[test]
+fn doctors_protect_before_the_mafia_kill() {
+    let (mut state, conns) = game(&["ann", "bob", "cat", "dan", "eve"]);
+    let roles = start(&mut state, "ann", &[(Role::Mafioso, 1), (Role::Doctor, 1)]);
+    let mafioso = player_with(&roles, Role::Mafioso).remove(0);
+    let doctor = player_with(&roles, Role::Doctor).remove(0);
+    let townie = player_with(&roles, Role::Townie).remove(0);
+
+    advance_to(&mut state, PhaseName::Night);
+    let protect = serde_json::json!({ "type": "Protect", "target": townie });
+    state.handle_action(doctor, act(protect)).unwrap();
+    let kill = serde_json::json!({ "type": "Kill", "target": townie });
+    state.handle_action(mafioso, act(kill)).unwrap();
+    advance_to(&mut state, PhaseName::Morning);
+
+    let outcome = last_night(&state);
+    assert_eq!(outcome.attacked.as_ref(), Some(&townie));
+    assert!(outcome.saved);
+    assert!(conns[&townie].received("Saved"));
+    assert!(!state.events().iter().any(|e| match e {
+        Event::Died { player, .. } => *player == townie,
+        _ => false,
+    }));
+}
+
+#[test]
+fn start_checks_the_roles_against_everyone_who_joined() {
+    let (mut state, _) = game(&["ann", "bob", "cat", "dan", "eve", "fay"]);
+    let update = serde_json::json!({
+        "type": "UpdateRules",
+        "rules": {
+            "min_players": 5,
+            "roles": { "kind": "Ratio", "ratios": [{ "role": "Mafioso", "per": 2 }] },
+        },
+    });
+    state.handle_action("ann".into(), act(update)).unwrap();
+    let start = serde_json::json!({ "type": "Start" });
+    let err = state.handle_action("ann".into(), act(start)).unwrap_err();
+    assert!(matches!(err, Error::InvalidRules(_)));
+    assert_eq!(state.summary().phase, PhaseName::Lobby);
+}
+
+fn dead_players(state: &State<Conn>) -> Vec<String> {
+    state
+        .events()
+        .iter()
+        .filter_map(|e| match e {
+            Event::Died { player, .. } => Some(player.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Has `ghost` try everything they might use to talk to `target`, and checks
+/// none of it reached anyone still alive.
+fn haunt(state: &mut State<Conn>, conns: &BTreeMap<String, Conn>, ghost: &str, target: &str) {
+    for conn in conns.values() {
+        conn.0.borrow_mut().clear();
+    }
+    let attempts = vec![
+        serde_json::json!({ "type": "Message", "text": "boo" }),
+        serde_json::json!({ "type": "Whisper", "to": target, "text": "boo" }),
+        serde_json::json!({ "type": "Vote", "target": target }),
+        serde_json::json!({ "type": "Kill", "target": target }),
+    ];
+    for attempt in attempts {
+        let _ = state.handle_action(ghost.into(), act(attempt));
+    }
+    let dead = dead_players(state);
+    for (name, conn) in conns.iter().filter(|(name, _)| !dead.contains(name)) {
+        for resp in conn.0.borrow().iter() {
+            assert_ne!(resp["channel"], "Dead", "{} heard {}", name, resp);
+            assert_ne!(resp["from"], ghost, "{} heard {}", name, resp);
+        }
+    }
+}
+
+#[test]
+fn the_dead_are_never_heard_by_the_living() {
+    for spectate in [false, true].iter() {
+        let names = ["ann", "bob", "cat", "dan", "eve", "fay"];
+        let (mut state, conns) = game(&names);
+        let update = serde_json::json!({
+            "type": "UpdateRules",
+            "rules": { "dead_spectate": spectate },
+        });
+        state.handle_action("ann".into(), act(update)).unwrap();
+        let roles = start(&mut state, "ann", &[(Role::Mafioso, 1)]);
+        let mafioso = player_with(&roles, Role::Mafioso).remove(0);
+        let town = player_with(&roles, Role::Townie);
+        let (ghost, condemned) = (&town[0], &town[1]);
+
+        advance_to(&mut state, PhaseName::Night);
+        let kill = serde_json::json!({ "type": "Kill", "target": ghost });
+        state.handle_action(mafioso.clone(), act(kill)).unwrap();
+        advance_to(&mut state, PhaseName::Morning);
+        assert_eq!(dead_players(&state), vec![ghost.clone()]);
+        haunt(&mut state, &conns, ghost, &mafioso);
+
+        advance_to(&mut state, PhaseName::Vote);
+        haunt(&mut state, &conns, ghost, &mafioso);
+        for voter in roles.keys().filter(|v| *v != ghost && *v != condemned) {
+            if state.summary().phase != PhaseName::Vote {
+                break;
+            }
+            let vote = serde_json::json!({ "type": "Vote", "target": condemned });
+            state.handle_action(voter.clone(), act(vote)).unwrap();
+        }
+        advance_to(&mut state, PhaseName::LastWords);
+        haunt(&mut state, &conns, ghost, &mafioso);
+
+        advance_to(&mut state, PhaseName::Evening);
+        assert!(dead_players(&state).contains(condemned));
+        haunt(&mut state, &conns, ghost, &mafioso);
+        assert!(conns[condemned]
+            .0
+            .borrow()
+            .iter()
+            .any(|r| r["channel"] == "Dead" && r["from"] == **ghost));
+        haunt(&mut state, &conns, condemned, &mafioso);
+
+        advance_to(&mut state, PhaseName::Night);
+        haunt(&mut state, &conns, ghost, &mafioso);
+        haunt(&mut state, &conns, condemned, &mafioso);
+    }
+}
+
+/// The roles `viewer` can see for other players, by name.
+fn visible_roles(state: &State<Conn>, viewer: &str) -> BTreeMap<String, Role> {
+    let view = state.view_for(viewer).unwrap();
+    assert!(serde_json::to_value(&view).unwrap()["rules"]["seed"].is_null());
+    view.players
+        .into_iter()
+        .filter_map(|p| Some((p.name, p.role?)))
+        .collect()
+}
+
+#[test]
+fn views_only_show_what_a_player_may_know() {
+    for reveal in [true, false].iter() {
+        let names = ["ann", "bob", "cat", "dan", "eve", "fay", "gus"];
+        let (mut state, _) = game(&names);
+        let update = serde_json::json!({
+            "type": "UpdateRules",
+            "rules": { "reveal_on_death": reveal },
+        });
+        state.handle_action("ann".into(), act(update)).unwrap();
+        for name in names.iter() {
+            assert!(state.view_for(name).unwrap().role.is_none());
+            assert!(visible_roles(&state, name).is_empty());
+        }
+
+        let roles = start(&mut state, "ann", &[(Role::Mafioso, 2)]);
+        let mafia = player_with(&roles, Role::Mafioso);
+        let town = player_with(&roles, Role::Townie);
+        for name in names.iter() {
+            let view = state.view_for(name).unwrap();
+            assert_eq!(view.role, Some(roles[*name]));
+            if mafia.contains(&name.to_string()) {
+                let other = mafia.iter().find(|m| *m != name).unwrap();
+                assert_eq!(view.allies, vec![other.clone()]);
+            } else {
+                assert!(view.allies.is_empty());
+            }
+            assert!(visible_roles(&state, name).is_empty());
+        }
+
+        advance_to(&mut state, PhaseName::Night);
+        let kill = serde_json::json!({ "type": "Kill", "target": town[0] });
+        state.handle_action(mafia[0].clone(), act(kill)).unwrap();
+        advance_to(&mut state, PhaseName::Morning);
+        for name in names.iter() {
+            let visible = visible_roles(&state, name);
+            if *reveal {
+                let expected: BTreeMap<_, _> =
+                    vec![(town[0].clone(), Role::Townie)].into_iter().collect();
+                assert_eq!(visible, expected);
+            } else {
+                assert!(visible.is_empty());
+            }
+        }
+
+        while state.summary().phase != PhaseName::GameOver {
+            if state.summary().phase == PhaseName::Night {
+                for target in &town {
+                    let kill = serde_json::json!({ "type": "Kill", "target": target });
+                    if state.handle_action(mafia[0].clone(), act(kill)).is_ok() {
+                        break;
+                    }
+                }
+            }
+            state.tick(state.next_state_time().unwrap());
+        }
+        for name in names.iter() {
+            assert_eq!(visible_roles(&state, name), roles);
+        }
+    }
+}
+
+fn vote(state: &mut State<Conn>, voter: &str, target: &str) {
+    let vote = serde_json::json!({ "type": "Vote", "target": target });
+    state.handle_action(voter.into(), act(vote)).unwrap();
+}
+
+fn last_vote(state: &State<Conn>) -> Option<String> {
+    state
+        .events()
+        .iter()
+        .rev()
+        .find_map(|e| match e {
+            Event::VoteResolved { condemned, .. } => Some(condemned.clone()),
+            _ => None,
+        })
+        .unwrap()
+}
+
+#[test]
+fn a_majority_condemns_straight_away() {
+    let (mut state, _) = game(&["ann", "bob", "cat", "dan", "eve"]);
+    start(&mut state, "ann", &[(Role::Mafioso, 1)]);
+    advance_to(&mut state, PhaseName::Vote);
+    vote(&mut state, "ann", "eve");
+    vote(&mut state, "bob", "eve");
+    assert_eq!(state.summary().phase, PhaseName::Vote);
+    vote(&mut state, "cat", "eve");
+    assert_eq!(state.summary().phase, PhaseName::LastWords);
+    assert_eq!(last_vote(&state), Some("eve".to_string()));
+    let view = state.view_for("ann").unwrap();
+    assert_eq!(view.condemned, Some("eve".to_string()));
+}
+
+#[test]
+fn the_most_votes_condemn_when_time_runs_out() {
+    let (mut state, _) = game(&["ann", "bob", "cat", "dan", "eve"]);
+    start(&mut state, "ann", &[(Role::Mafioso, 1)]);
+    advance_to(&mut state, PhaseName::Vote);
+    vote(&mut state, "ann", "eve");
+    vote(&mut state, "bob", "eve");
+    vote(&mut state, "cat", "dan");
+    advance_to(&mut state, PhaseName::LastWords);
+    assert_eq!(last_vote(&state), Some("eve".to_string()));
+}
+
+#[test]
+fn a_tied_vote_lynches_nobody() {
+    let (mut state, _) = game(&["ann", "bob", "cat", "dan", "eve"]);
+    start(&mut state, "ann", &[(Role::Mafioso, 1)]);
+    advance_to(&mut state, PhaseName::Vote);
+    vote(&mut state, "ann", "eve");
+    vote(&mut state, "bob", "eve");
+    vote(&mut state, "cat", "dan");
+    vote(&mut state, "dan", "cat");
+    vote(&mut state, "eve", "dan");
+    let deadline = state.next_state_time().unwrap();
+    state.tick(deadline);
+    assert_eq!(state.summary().phase, PhaseName::Evening);
+    assert_eq!(last_vote(&state), None);
+    assert!(dead_players(&state).is_empty());
+}
+
+fn skip(state: &mut State<Conn>, player: &str) {
+    let skip = serde_json::json!({ "type": "SkipVote" });
+    state.handle_action(player.into(), act(skip)).unwrap();
+}
+
+fn set_skip_threshold(state: &mut State<Conn>, threshold: f64) {
+    let update = serde_json::json!({
+        "type": "UpdateRules",
+        "rules": { "skip_threshold": threshold },
+    });
+    state.handle_action("ann".into(), act(update)).unwrap();
+}
+
+#[test]
+fn skip_thresholds_round_up() {
+    let (mut state, conns) = game(&["ann", "bob", "cat", "dan", "eve"]);
+    set_skip_threshold(&mut state, 0.5);
+    start(&mut state, "ann", &[(Role::Mafioso, 1)]);
+    skip(&mut state, "ann");
+    skip(&mut state, "bob");
+    assert_eq!(state.summary().phase, PhaseName::Morning);
+    let required = conns["ann"]
+        .0
+        .borrow()
+        .iter()
+        .rev()
+        .find(|r| r["type"] == "SkipVotes")
+        .map(|r| r["required"].clone());
+    assert_eq!(required, Some(serde_json::json!(3)));
+    skip(&mut state, "cat");
+    assert_eq!(state.summary().phase, PhaseName::Vote);
+
+    vote(&mut state, "dan", "eve");
+    skip(&mut state, "ann");
+    skip(&mut state, "bob");
+    assert_eq!(state.summary().phase, PhaseName::Vote);
+    skip(&mut state, "cat");
+    assert_eq!(state.summary().phase, PhaseName::Evening);
+    assert_eq!(last_vote(&state), None);
+}
+
+#[test]
+fn one_skip_is_always_needed() {
+    let (mut state, _) = game(&["ann", "bob", "cat", "dan", "eve"]);
+    set_skip_threshold(&mut state, 0.01);
+    start(&mut state, "ann", &[(Role::Mafioso, 1)]);
+    assert_eq!(state.summary().phase, PhaseName::Morning);
+    skip(&mut state, "ann");
+    assert_eq!(state.summary().phase, PhaseName::Vote);
+}
+
+fn winner(state: &State<Conn>) -> Option<Team> {
+    state.view_for("ann").unwrap().winner
+}
+
+#[test]
+fn the_town_wins_once_the_mafia_are_dead() {
+    let (mut state, conns) = game(&["ann", "bob", "cat", "dan", "eve"]);
+    let roles = start(&mut state, "ann", &[(Role::Mafioso, 1)]);
+    let mafioso = player_with(&roles, Role::Mafioso).remove(0);
+    advance_to(&mut state, PhaseName::Vote);
+    for voter in roles.keys().filter(|v| **v != mafioso).take(3) {
+        vote(&mut state, voter, &mafioso);
+    }
+    advance_to(&mut state, PhaseName::GameOver);
+    assert_eq!(winner(&state), Some(Team::Town));
+    assert!(conns["ann"].received("GameOver"));
+    let err = state.handle_action("ann".into(), act(serde_json::json!({ "type": "Unvote" })));
+    assert!(matches!(err, Err(Error::GameOver)));
+}
+
+#[test]
+fn the_mafia_win_once_they_match_the_town() {
+    let (mut state, _) = game(&["ann", "bob", "cat", "dan"]);
+    let roles = start(&mut state, "ann", &[(Role::Mafioso, 1)]);
+    let mafioso = player_with(&roles, Role::Mafioso).remove(0);
+    let town = player_with(&roles, Role::Townie);
+    advance_to(&mut state, PhaseName::Vote);
+    for voter in roles.keys().filter(|v| **v != town[0]).take(3) {
+        vote(&mut state, voter, &town[0]);
+    }
+    advance_to(&mut state, PhaseName::Night);
+    assert_eq!(winner(&state), None);
+    let kill = serde_json::json!({ "type": "Kill", "target": town[1] });
+    state.handle_action(mafioso, act(kill)).unwrap();
+    advance_to(&mut state, PhaseName::GameOver);
+    assert_eq!(winner(&state), Some(Team::Mafia));
+}
+
+#[test]
+fn whispers_reach_only_the_two_players_involved() {
+    let (mut state, conns) = game(&["ann", "bob", "cat", "dan", "eve"]);
+    start(&mut state, "ann", &[(Role::Mafioso, 1)]);
+    let whisper = serde_json::json!({ "type": "Whisper", "to": "bob", "text": "psst" });
+    state.handle_action("ann".into(), act(whisper)).unwrap();
+    let heard = |name: &str| {
+        conns[name]
+            .0
+            .borrow()
+            .iter()
+            .any(|r| r["channel"] == "Whisper" && r["text"] == "psst")
+    };
+    assert!(heard("ann"));
+    assert!(heard("bob"));
+    assert!(!heard("cat"));
+    assert!(conns["cat"].received("WhisperNotice"));
+    assert!(!conns["ann"].received("WhisperNotice"));
+}
@@ -0,0 +1,95 @@
+#![cfg(feature = "schema")]
+
+use jsonschema::JSONSchema;
+use mafia::{Action, Error, PlayerConnection, Response, RuleViolation, Ruleset, State};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Remembers everything sent to it, as json.
+#[derive(Clone, Default)]
+struct Conn(Rc<RefCell<Vec<serde_json::Value>>>);
+
+impl PlayerConnection for Conn {
+    fn send(&self, resp: Response) {
+        self.0
+            .borrow_mut()
+            .push(serde_json::to_value(resp).unwrap());
+    }
+
+    fn is_alive(&self) -> bool {
+        true
+    }
+
+    fn close(&self) {}
+}
+
+fn act(json: serde_json::Value) -> Action {
+    serde_json::from_value(json).unwrap()
+}
+
+fn assert_valid(schema: &JSONSchema, resp: &serde_json::Value) {
+    if let Err(errors) = schema.validate(resp) {
+        let errors: Vec<_> = errors.map(|e| e.to_string()).collect();
+        panic!("{} doesn't match the schema: {:?}", resp, errors);
+    }
+}
+
+#[test]
+fn errors_match_the_response_schema() {
+    let schema = serde_json::to_value(mafia::schema::response()).unwrap();
+    let schema = JSONSchema::compile(&schema).unwrap();
+    let errors = vec![
+        Error::WrongPhase,
+        Error::InvalidPlayerName("x".into()),
+        Error::NotEnoughPlayers {
+            required: 4,
+            joined: 2,
+        },
+        Error::InvalidRules(vec![RuleViolation::NoMafia]),
+    ];
+    for e in errors {
+        assert_valid(&schema, &serde_json::to_value(Response::from(e)).unwrap());
+    }
+}
+
+#[test]
+fn a_whole_game_matches_the_response_schema() {
+    let schema = serde_json::to_value(mafia::schema::response()).unwrap();
+    let schema = JSONSchema::compile(&schema).unwrap();
+
+    let log = Conn::default();
+    let names = ["ann", "bob", "cat", "dan", "eve"];
+    let mut state = State::new(Ruleset::default(), names[0].into(), "secret0".into());
+    for (i, name) in names.iter().enumerate() {
+        if i > 0 {
+            state
+                .create_user(name.to_string(), format!("secret{}", i))
+                .unwrap();
+        }
+        state.register_connection(name.to_string(), Some(log.clone()));
+    }
+    let start = serde_json::json!({ "type": "Start" });
+    state.handle_action("ann".into(), act(start)).unwrap();
+    for round in 0..100 {
+        let deadline = match state.next_state_time() {
+            Some(deadline) => deadline,
+            None => break,
+        };
+        for (i, name) in names.iter().enumerate() {
+            let say = serde_json::json!({ "type": "Message", "text": "hi" });
+            let _ = state.handle_action(name.to_string(), act(say));
+            let target = names[(i + round + 1) % names.len()];
+            for kind in ["Vote", "Kill", "Protect", "Roleblock", "Investigate"].iter() {
+                let action = serde_json::json!({ "type": kind, "target": target });
+                let _ = state.handle_action(name.to_string(), act(action));
+            }
+        }
+        state.tick(deadline);
+    }
+
+    let sent = log.0.borrow();
+    assert!(sent.iter().any(|r| r["type"] == "GameOver"));
+    for resp in sent.iter() {
+        assert_valid(&schema, resp);
+    }
+}